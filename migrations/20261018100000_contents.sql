@@ -0,0 +1,29 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS contents (
+    content_hash TEXT PRIMARY KEY NOT NULL,
+    content TEXT NOT NULL,
+    ref_count BIGINT NOT NULL
+);
+
+-- existing bodies can't be hashed from SQL, so they keep a unique legacy key
+INSERT INTO contents (content_hash, content, ref_count)
+    SELECT 'legacy-' || clip_id, content, 1 FROM clips;
+
+CREATE TABLE clips_new (
+    clip_id TEXT PRIMARY KEY NOT NULL,
+    short_code TEXT UNIQUE NOT NULL,
+    content_hash TEXT NOT NULL REFERENCES contents (content_hash),
+    title TEXT,
+    posted_at DATETIME NOT NULL,
+    expires_at DATETIME,
+    password TEXT,
+    hits BIGINT NOT NULL
+);
+
+INSERT INTO clips_new (clip_id, short_code, content_hash, title, posted_at, expires_at, password, hits)
+    SELECT clip_id, short_code, 'legacy-' || clip_id, title, posted_at, expires_at, password, hits FROM clips;
+
+DROP TABLE clips;
+ALTER TABLE clips_new RENAME TO clips;
+
+CREATE INDEX IF NOT EXISTS clips_content_hash ON clips (content_hash);
@@ -0,0 +1,30 @@
+-- Add migration script here
+CREATE TABLE contents_new (
+    content_hash TEXT PRIMARY KEY NOT NULL,
+    content BLOB NOT NULL,
+    compressed BOOLEAN NOT NULL DEFAULT FALSE,
+    ref_count BIGINT NOT NULL
+);
+
+INSERT INTO contents_new (content_hash, content, compressed, ref_count)
+    SELECT content_hash, CAST(content AS BLOB), FALSE, ref_count FROM contents;
+
+CREATE TABLE clips_new (
+    clip_id TEXT PRIMARY KEY NOT NULL,
+    short_code TEXT UNIQUE NOT NULL,
+    content_hash TEXT NOT NULL REFERENCES contents_new (content_hash),
+    title TEXT,
+    posted_at DATETIME NOT NULL,
+    expires_at DATETIME,
+    password TEXT,
+    hits BIGINT NOT NULL
+);
+
+INSERT INTO clips_new SELECT * FROM clips;
+
+DROP TABLE clips;
+DROP TABLE contents;
+ALTER TABLE contents_new RENAME TO contents;
+ALTER TABLE clips_new RENAME TO clips;
+
+CREATE INDEX IF NOT EXISTS clips_content_hash ON clips (content_hash);
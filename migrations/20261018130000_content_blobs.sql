@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE contents ADD COLUMN on_disk BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS tags
+(
+    tag_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name   TEXT    NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS clip_tags
+(
+    clip_id TEXT    NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    tag_id  INTEGER NOT NULL REFERENCES tags (tag_id),
+    PRIMARY KEY (clip_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS clip_tags_tag_id ON clip_tags (tag_id);
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS collections (
+    collection_id TEXT PRIMARY KEY NOT NULL,
+    short_code TEXT UNIQUE NOT NULL,
+    title TEXT,
+    password TEXT,
+    posted_at DATETIME NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS collection_clips (
+    collection_id TEXT NOT NULL REFERENCES collections (collection_id) ON DELETE CASCADE,
+    clip_id TEXT NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, clip_id)
+);
@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN forked_from TEXT;
+
+CREATE INDEX clips_forked_from ON clips (forked_from);
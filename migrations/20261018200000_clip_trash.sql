@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN deleted_at DATETIME;
+
+CREATE INDEX clips_deleted_at ON clips (deleted_at);
@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS clip_views (
+    clip_id TEXT NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    views BIGINT NOT NULL,
+    PRIMARY KEY (clip_id, day)
+);
@@ -0,0 +1,9 @@
+-- Add migration script here
+ALTER TABLE clip_views ADD COLUMN unique_views BIGINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS clip_visitors (
+    clip_id TEXT NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    visitor TEXT NOT NULL,
+    PRIMARY KEY (clip_id, day, visitor)
+);
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE clips ADD COLUMN detected_language TEXT;
@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS server_secrets (
+    name TEXT PRIMARY KEY NOT NULL,
+    secret BLOB NOT NULL
+);
@@ -1,13 +1,19 @@
 use clipstash::{
-    domain::clip::field::{Content, ExpiresAt, Password, Title},
-    service::ask::{GetClip, NewClip, UpdateClip},
+    domain::clip::{
+        field::{Content, ExpiresAt, Language, Password, Tags, Title},
+        ClipSummary,
+    },
+    service::ask::{GetClip, NewClip, Patch, PatchClip},
     web::{
-        api::{ApiKey, API_KEY_HEADER},
+        api::{ApiKey, ErrorBody, API_KEY_HEADER},
         PASSWORD_COOKIE,
     },
     Clip, ShortCode,
 };
+use reqwest::blocking::Response;
+use serde::de::DeserializeOwned;
 use std::error::Error;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -20,24 +26,60 @@ enum Command {
 
     New {
         #[structopt(help = "content")]
-        clip: String,
+        clip: Option<String>,
+        #[structopt(short, long, parse(from_os_str), help = "file to attach")]
+        file: Option<PathBuf>,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
         #[structopt(short, long, help = "expiration date")]
         expires_at: Option<ExpiresAt>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(short = "g", long, help = "comma separated tags")]
+        tags: Option<Tags>,
+        #[structopt(short, long, help = "language to highlight as, detected when left out")]
+        language: Option<Language>,
+    },
+
+    Fork {
+        short_code: ShortCode,
+        #[structopt(short, long, help = "password of the original clip")]
+        password: Option<String>,
+    },
+
+    List {
+        #[structopt(short = "g", long, help = "only list clips with this tag")]
+        tag: Option<String>,
     },
 
     Update {
         short_code: ShortCode,
-        clip: String,
+        #[structopt(help = "content")]
+        clip: Option<String>,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
         #[structopt(short, long, help = "expiration date")]
         expires_at: Option<ExpiresAt>,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(long, conflicts_with = "password", help = "remove the password")]
+        clear_password: bool,
+        #[structopt(long, conflicts_with = "expires-at", help = "never expire")]
+        clear_expires_at: bool,
+        #[structopt(long, conflicts_with = "title", help = "remove the title")]
+        clear_title: bool,
+        #[structopt(short, long, help = "language to highlight as")]
+        language: Option<Language>,
+        #[structopt(long, conflicts_with = "language", help = "detect the language")]
+        clear_language: bool,
+        #[structopt(long, help = "only update if the clip is still at this version")]
+        if_version: Option<u64>,
+    },
+
+    Watch {
+        short_code: ShortCode,
+        #[structopt(short, long, help = "password")]
+        password: Option<String>,
     },
 }
 
@@ -54,6 +96,19 @@ struct Opt {
     api_key: ApiKey,
 }
 
+/// Decodes a successful response, or the API's error body otherwise.
+fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T, Box<dyn Error>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json()?);
+    }
+
+    match response.json::<ErrorBody>() {
+        Ok(err) => Err(err.into()),
+        Err(_) => Err(format!("request failed: {}", status).into()),
+    }
+}
+
 fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
     let addr = format!("{}/api/clip/{}", addr, ask_svc.short_code.into_inner());
@@ -69,7 +124,43 @@ fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<d
 
     request = request.header(API_KEY_HEADER, api_key.to_base64());
 
-    Ok(request.send()?.json()?)
+    parse_response(request.send()?)
+}
+
+fn fork_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}/fork", addr, ask_svc.short_code.into_inner());
+    let mut request = client.post(addr);
+
+    request = match ask_svc.password.into_inner() {
+        Some(password) => request.header(
+            reqwest::header::COOKIE,
+            format!("{}={}", PASSWORD_COOKIE, password),
+        ),
+        None => request,
+    };
+
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+
+    parse_response(request.send()?)
+}
+
+fn list_clips(
+    addr: &str,
+    tag: Option<String>,
+    api_key: ApiKey,
+) -> Result<Vec<ClipSummary>, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip", addr);
+    let mut request = client.get(addr);
+
+    if let Some(tag) = tag {
+        request = request.query(&[("tag", tag)]);
+    }
+
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+
+    parse_response(request.send()?)
 }
 
 fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
@@ -79,17 +170,118 @@ fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<d
 
     request = request.header(API_KEY_HEADER, api_key.to_base64());
 
-    Ok(request.json(&ask_svc).send()?.json()?)
+    parse_response(request.json(&ask_svc).send()?)
 }
 
-fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn new_clip_with_file(
+    addr: &str,
+    ask_svc: NewClip,
+    file: PathBuf,
+    api_key: ApiKey,
+) -> Result<Clip, Box<dyn Error>> {
+    use reqwest::blocking::multipart::Form;
+
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
-    let mut request = client.put(addr);
+    let addr = format!("{}/api/clip/file", addr);
+    let expires_at = ask_svc
+        .expires_at
+        .into_inner()
+        .map(|time| time.into_inner().format("%Y-%m-%d").to_string());
+    let form = Form::new()
+        .text("content", ask_svc.content.into_inner())
+        .text("title", ask_svc.title.into_inner().unwrap_or_default())
+        .text("expires_at", expires_at.unwrap_or_default())
+        .text(
+            "password",
+            ask_svc.password.into_inner().unwrap_or_default(),
+        )
+        .text("tags", ask_svc.tags.into_inner().join(","))
+        .text(
+            "language",
+            ask_svc.language.into_inner().unwrap_or_default(),
+        )
+        .file("attachment", file)?;
+    let mut request = client.post(addr);
+
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+
+    parse_response(request.multipart(form).send()?)
+}
+
+fn patch_clip(
+    addr: &str,
+    short_code: ShortCode,
+    ask_svc: PatchClip,
+    if_version: Option<u64>,
+    api_key: ApiKey,
+) -> Result<Clip, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/clip/{}", addr, short_code.into_inner());
+    let mut request = client.patch(addr);
 
     request = request.header(API_KEY_HEADER, api_key.to_base64());
+    if let Some(version) = if_version {
+        request = request.header("If-Match", format!("\"{}\"", version));
+    }
+
+    parse_response(request.json(&ask_svc).send()?)
+}
+
+/// Prints every change to a clip until it is deleted or locked.
+fn watch_clip(
+    addr: &str,
+    short_code: ShortCode,
+    password: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    use std::io::{BufRead, BufReader};
+
+    let client = reqwest::blocking::Client::builder().timeout(None).build()?;
+    let addr = format!("{}/clip/{}/events", addr, short_code.into_inner());
+    let mut request = client.get(addr);
+
+    if let Some(password) = password {
+        request = request.header(
+            reqwest::header::COOKIE,
+            format!("{}={}", PASSWORD_COOKIE, password),
+        );
+    }
+
+    let response = request.send()?;
+    if !response.status().is_success() {
+        return Err(format!("failed to watch clip: {}", response.status()).into());
+    }
+
+    let mut event = String::new();
+    let mut data = String::new();
+    for line in BufReader::new(response).lines() {
+        let line = line?;
+        if let Some(name) = line.strip_prefix("event:") {
+            event = name.trim().to_owned();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            if !data.is_empty() {
+                data.push('\n');
+            }
+            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        } else if line.is_empty() {
+            match event.as_str() {
+                "updated" => {
+                    let clip: serde_json::Value = serde_json::from_str(&data)?;
+                    println!("--- version {} ---", clip["version"]);
+                    println!("{}", clip["content"].as_str().unwrap_or_default());
+                }
+                "deleted" => {
+                    println!("--- clip deleted ---");
+                    return Ok(());
+                }
+                "locked" => return Err("clip is now password protected".into()),
+                _ => (),
+            }
+            event.clear();
+            data.clear();
+        }
+    }
 
-    Ok(request.json(&ask_svc).send()?.json()?)
+    Ok(())
 }
 
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
@@ -100,7 +292,7 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
         } => {
             let req = GetClip {
                 password: Password::new(password.unwrap_or_default())?,
-                short_code: short_code,
+                short_code,
             };
             let clip = get_clip(opt.addr.as_str(), req, opt.api_key)?;
 
@@ -110,47 +302,112 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
 
         Command::New {
             clip,
+            file,
             expires_at,
             password,
             title,
+            tags,
+            language,
         } => {
+            let content = match (clip, &file) {
+                (Some(clip), _) => clip,
+                (None, Some(file)) => file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                (None, None) => String::new(),
+            };
             let req = NewClip {
-                content: Content::new(clip.as_str())?,
+                content: Content::new(content.as_str())?,
                 title: title.unwrap_or_default(),
                 expires_at: expires_at.unwrap_or_default(),
                 password: password.unwrap_or_default(),
+                tags: tags.unwrap_or_default(),
+                language: language.unwrap_or_default(),
+            };
+            let clip = match file {
+                Some(file) => new_clip_with_file(opt.addr.as_str(), req, file, opt.api_key)?,
+                None => new_clip(opt.addr.as_str(), req, opt.api_key)?,
             };
-            let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
 
             println!("{:#?}", clip);
             Ok(())
         }
 
+        Command::Fork {
+            short_code,
+            password,
+        } => {
+            let req = GetClip {
+                password: Password::new(password.unwrap_or_default())?,
+                short_code,
+            };
+            let clip = fork_clip(opt.addr.as_str(), req, opt.api_key)?;
+
+            println!("{:#?}", clip);
+            Ok(())
+        }
+
+        Command::List { tag } => {
+            for clip in list_clips(opt.addr.as_str(), tag, opt.api_key)? {
+                let tags: Vec<_> = clip.tags.iter().collect();
+                println!(
+                    "{}\t{}\t[{}]",
+                    clip.short_code.as_str(),
+                    clip.title.into_inner().unwrap_or_default(),
+                    tags.join(", ")
+                );
+            }
+            Ok(())
+        }
+
         Command::Update {
             clip,
             expires_at,
             password,
             short_code,
             title,
+            clear_password,
+            clear_expires_at,
+            clear_title,
+            language,
+            clear_language,
+            if_version,
         } => {
-            let password = password.unwrap_or_default();
-            let svc_req = GetClip {
-                password: password.clone(),
-                short_code: short_code.clone(),
+            fn patch<T>(value: Option<T>, clear: bool) -> Patch<T> {
+                match (value, clear) {
+                    (Some(value), _) => Patch::Set(value),
+                    (None, true) => Patch::Clear,
+                    (None, false) => Patch::Keep,
+                }
+            }
+
+            let svc_req = PatchClip {
+                content: patch(
+                    clip.map(|clip| Content::new(clip.as_str())).transpose()?,
+                    false,
+                ),
+                expires_at: patch(expires_at, clear_expires_at),
+                title: patch(title, clear_title),
+                password: patch(password, clear_password),
+                language: patch(language, clear_language),
             };
-            let original_clip = get_clip(opt.addr.as_str(), svc_req, opt.api_key.clone())?;
-            let svc_req = UpdateClip {
-                content: Content::new(clip.as_str())?,
-                expires_at: expires_at.unwrap_or(original_clip.expires_at),
-                title: title.unwrap_or(original_clip.title),
-                password,
+            let clip = patch_clip(
+                opt.addr.as_str(),
                 short_code,
-            };
-            let clip = update_clip(opt.addr.as_str(), svc_req, opt.api_key)?;
+                svc_req,
+                if_version,
+                opt.api_key,
+            )?;
 
             println!("{:#?}", clip);
             Ok(())
         }
+
+        Command::Watch {
+            short_code,
+            password,
+        } => watch_clip(opt.addr.as_str(), short_code, password),
     }
 }
 
@@ -0,0 +1,142 @@
+use clipstash::{
+    data::{content, AppDatabase},
+    service::action,
+};
+use dotenv::dotenv;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+use structopt::StructOpt;
+use tokio::runtime::Runtime;
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    #[structopt(about = "re-encode stored content using the compression threshold")]
+    Recompress {
+        #[structopt(
+            long,
+            default_value = "16384",
+            env = "CLIPSTASH_COMPRESSION_THRESHOLD",
+            help = "size in bytes above which clip content is compressed"
+        )]
+        compression_threshold: usize,
+    },
+
+    #[structopt(about = "guess the language of clips saved before guesses were stored")]
+    DetectLanguages,
+
+    #[structopt(
+        about = "re-key bodies from before content deduplication by their hash; httpd also does this on startup"
+    )]
+    RehashLegacy,
+
+    #[structopt(about = "find orphaned blob files and missing blobs")]
+    CheckBlobs {
+        #[structopt(long, help = "delete orphaned blob files")]
+        remove_orphans: bool,
+    },
+
+    #[structopt(
+        about = "delete clips that have been in the trash for longer than the grace period"
+    )]
+    PurgeTrash {
+        #[structopt(
+            long,
+            default_value = "168",
+            env = "CLIPSTASH_TRASH_GRACE_PERIOD",
+            help = "hours expired clips stay in the trash before they are purged"
+        )]
+        grace_period: u64,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "clip_maintenance", about = "ClipStash maintenance tasks")]
+struct Opt {
+    #[structopt(subcommand)]
+    command: Command,
+
+    #[structopt(long, default_value = "sqlite:data.db", env = "DATABASE_URL")]
+    connection_string: String,
+
+    #[structopt(long, parse(from_os_str), env = "CLIPSTASH_BLOB_DIR")]
+    blob_dir: Option<PathBuf>,
+
+    #[structopt(long, default_value = "1048576", env = "CLIPSTASH_BLOB_THRESHOLD")]
+    blob_threshold: usize,
+}
+
+async fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
+    let database = AppDatabase::new(&opt.connection_string).await;
+    let mut store = content::ContentStore {
+        blobs: opt.blob_dir.map(|dir| content::BlobStore {
+            dir,
+            threshold: opt.blob_threshold,
+        }),
+        ..Default::default()
+    };
+
+    match opt.command {
+        Command::Recompress {
+            compression_threshold,
+        } => {
+            store.compression_threshold = compression_threshold;
+            let recompressed = action::recompress_contents(&store, database.get_pool()).await?;
+
+            println!("recompressed {} rows", recompressed);
+            Ok(())
+        }
+
+        Command::DetectLanguages => {
+            let detected = action::detect_languages(&store, database.get_pool()).await?;
+
+            println!("detected the language of {} clips", detected);
+            Ok(())
+        }
+
+        Command::RehashLegacy => {
+            let rehashed = action::rehash_legacy_contents(&store, database.get_pool()).await?;
+
+            println!("rehashed {} legacy contents", rehashed);
+            Ok(())
+        }
+
+        Command::CheckBlobs { remove_orphans } => {
+            let report = action::check_blobs(remove_orphans, &store, database.get_pool()).await?;
+
+            for file in report.orphaned_files.iter() {
+                println!("orphaned file: {}", file);
+            }
+            for content_hash in report.dangling_references.iter() {
+                println!("missing blob: {}", content_hash);
+            }
+            println!(
+                "{} orphaned files{}, {} missing blobs",
+                report.orphaned_files.len(),
+                if remove_orphans { " removed" } else { "" },
+                report.dangling_references.len()
+            );
+            Ok(())
+        }
+
+        Command::PurgeTrash { grace_period } => {
+            let grace_period = Duration::from_secs(grace_period.saturating_mul(60 * 60));
+            let purged = action::purge_trash(grace_period, database.get_pool()).await?;
+            action::delete_unreferenced_contents(&store, database.get_pool()).await?;
+
+            println!("purged {} clips", purged);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    dotenv().ok();
+
+    let opt = Opt::from_args();
+    let rt = Runtime::new().expect("failed to spawn runtime");
+
+    if let Err(err) = rt.block_on(run(opt)) {
+        eprintln!("failed to run: {}", err);
+    }
+}
@@ -1,10 +1,21 @@
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clipstash::{
-    data::AppDatabase,
-    domain::maintenance::Maintenance,
+    data::{content, AppDatabase},
+    domain::{
+        clip::limits::ClipLimits,
+        maintenance::{Maintenance, MaintenanceConfig},
+        webhook::{WebhookConfig, Webhooks},
+    },
     rocket,
-    web::{hit_counter::HitCounter, renderer::Renderer},
+    service::{action, events::ClipEvents},
+    web::{
+        hit_counter::{HitCounter, HitCounterConfig},
+        metrics::Metrics,
+        renderer::Renderer,
+        PublicUrl,
+    },
     RocketConfig,
 };
 use dotenv::dotenv;
@@ -15,17 +26,78 @@ fn main() {
     dotenv().ok();
 
     let opt = Opt::from_args();
+    let content_store = content::ContentStore {
+        compression_threshold: opt.compression_threshold,
+        blobs: opt.blob_dir.clone().map(|dir| content::BlobStore {
+            dir,
+            threshold: opt.blob_threshold,
+        }),
+    };
+
     let rt = Runtime::new().expect("failed to spawn runtime");
     let handle = rt.handle().clone();
     let renderer = Renderer::new(opt.template_dir.clone());
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
-    let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
+    let connection_string = opt.connection_string.clone();
+    let startup_store = content_store.clone();
+    let (database, visitor_key) = rt.block_on(async move {
+        let database = AppDatabase::new(&connection_string).await;
+        // bodies from before content deduplication are stored under legacy keys
+        match action::rehash_legacy_contents(&startup_store, database.get_pool()).await {
+            Ok(0) => (),
+            Ok(rehashed) => println!("rehashed {} legacy contents", rehashed),
+            Err(err) => eprintln!("failed to rehash legacy contents: {}", err),
+        }
+        let visitor_key = action::visitor_key(database.get_pool())
+            .await
+            .expect("failed to load the visitor key");
+        (database, visitor_key)
+    });
+    let hit_counter = HitCounter::new(
+        database.get_pool().clone(),
+        handle.clone(),
+        HitCounterConfig {
+            capacity: opt.hit_queue_capacity,
+            flush_interval: Duration::from_secs(opt.hit_flush_interval),
+            flush_size: opt.hit_flush_size,
+            max_attempts: opt.hit_max_attempts,
+        },
+    );
+    let events = ClipEvents::default();
+    let maintenance = Maintenance::spawn(
+        database.get_pool().clone(),
+        content_store.clone(),
+        events.clone(),
+        handle.clone(),
+        MaintenanceConfig {
+            trash_grace_period: Duration::from_secs(opt.trash_grace_period.saturating_mul(60 * 60)),
+            ..Default::default()
+        },
+    );
+    let webhooks = Webhooks::spawn(
+        database.get_pool().clone(),
+        handle.clone(),
+        WebhookConfig {
+            max_attempts: opt.webhook_max_attempts,
+            timeout: Duration::from_secs(opt.webhook_timeout),
+            ..Default::default()
+        },
+    );
     let config = RocketConfig {
         renderer,
         database,
+        content_store,
+        events,
         hit_counter,
         maintenance,
+        webhooks,
+        metrics: Metrics::new(opt.metrics_token.clone()),
+        public_url: PublicUrl::new(opt.public_url.clone()),
+        visitor_key,
+        limits: ClipLimits {
+            max_content_size: opt.max_content_size,
+            max_title_length: opt.max_title_length,
+            max_attachment_size: opt.max_attachment_size,
+        },
     };
 
     rt.block_on(async move {
@@ -43,4 +115,107 @@ struct Opt {
     connection_string: String,
     #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
     template_dir: PathBuf,
+    #[structopt(
+        long,
+        default_value = "16384",
+        env = "CLIPSTASH_COMPRESSION_THRESHOLD",
+        help = "size in bytes above which clip content is compressed"
+    )]
+    compression_threshold: usize,
+    #[structopt(
+        long,
+        parse(from_os_str),
+        env = "CLIPSTASH_BLOB_DIR",
+        help = "directory for content stored outside the database"
+    )]
+    blob_dir: Option<PathBuf>,
+    #[structopt(
+        long,
+        default_value = "1048576",
+        env = "CLIPSTASH_BLOB_THRESHOLD",
+        help = "stored size in bytes above which content is written to the blob directory"
+    )]
+    blob_threshold: usize,
+    #[structopt(
+        long,
+        default_value = "524288",
+        env = "CLIPSTASH_MAX_CONTENT_SIZE",
+        help = "maximum clip content size in bytes"
+    )]
+    max_content_size: usize,
+    #[structopt(
+        long,
+        default_value = "100",
+        env = "CLIPSTASH_MAX_TITLE_LENGTH",
+        help = "maximum clip title length in characters"
+    )]
+    max_title_length: usize,
+    #[structopt(
+        long,
+        default_value = "4194304",
+        env = "CLIPSTASH_MAX_ATTACHMENT_SIZE",
+        help = "maximum attachment size in bytes"
+    )]
+    max_attachment_size: usize,
+    #[structopt(
+        long,
+        default_value = "8",
+        env = "CLIPSTASH_WEBHOOK_MAX_ATTEMPTS",
+        help = "delivery attempts before a webhook event is dead-lettered"
+    )]
+    webhook_max_attempts: u32,
+    #[structopt(
+        long,
+        default_value = "10",
+        env = "CLIPSTASH_WEBHOOK_TIMEOUT",
+        help = "seconds to wait for a webhook endpoint to respond"
+    )]
+    webhook_timeout: u64,
+    #[structopt(
+        long,
+        default_value = "168",
+        env = "CLIPSTASH_TRASH_GRACE_PERIOD",
+        help = "hours expired clips stay in the trash before they are purged"
+    )]
+    trash_grace_period: u64,
+    #[structopt(
+        long,
+        default_value = "4096",
+        env = "CLIPSTASH_HIT_QUEUE_CAPACITY",
+        help = "hits waiting to be counted before new ones are dropped"
+    )]
+    hit_queue_capacity: usize,
+    #[structopt(
+        long,
+        default_value = "5",
+        env = "CLIPSTASH_HIT_FLUSH_INTERVAL",
+        help = "seconds between hit count commits"
+    )]
+    hit_flush_interval: u64,
+    #[structopt(
+        long,
+        default_value = "256",
+        env = "CLIPSTASH_HIT_FLUSH_SIZE",
+        help = "clips with pending hits that trigger an early commit"
+    )]
+    hit_flush_size: usize,
+    #[structopt(
+        long,
+        default_value = "5",
+        env = "CLIPSTASH_HIT_MAX_ATTEMPTS",
+        help = "failed commits a batch of hits survives before it is dropped"
+    )]
+    hit_max_attempts: u32,
+    #[structopt(
+        long,
+        env = "CLIPSTASH_METRICS_TOKEN",
+        help = "bearer token required by /metrics; the endpoint is disabled without one"
+    )]
+    metrics_token: Option<String>,
+    #[structopt(
+        long,
+        env = "CLIPSTASH_PUBLIC_URL",
+        help = "base URL of links handed to raw pastes, e.g. https://paste.example.com; defaults to the listen address"
+    )]
+    public_url: Option<String>,
 }
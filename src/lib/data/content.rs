@@ -0,0 +1,186 @@
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bodies larger than this many bytes are deflated before being stored.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// Directory that stored bodies above `threshold` bytes are written to
+/// instead of the `contents` table.
+#[derive(Clone, Debug)]
+pub struct BlobStore {
+    pub dir: PathBuf,
+    pub threshold: usize,
+}
+
+/// How and where bodies are stored. It is managed by Rocket and handed to every
+/// query that reads or writes bodies, like the pool.
+#[derive(Clone, Debug)]
+pub struct ContentStore {
+    pub compression_threshold: usize,
+    pub blobs: Option<BlobStore>,
+}
+
+impl Default for ContentStore {
+    fn default() -> Self {
+        Self {
+            compression_threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            blobs: None,
+        }
+    }
+}
+
+impl ContentStore {
+    /// Converts a body into its stored form, along with whether it was compressed.
+    pub fn encode<C: AsRef<[u8]>>(&self, content: C) -> io::Result<(Vec<u8>, bool)> {
+        let content = content.as_ref();
+
+        if content.len() <= self.compression_threshold {
+            return Ok((content.to_vec(), false));
+        }
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content)?;
+
+        Ok((encoder.finish()?, true))
+    }
+
+    /// Whether an encoded body of `len` bytes belongs on disk.
+    pub fn is_blob(&self, len: usize) -> bool {
+        self.blobs
+            .as_ref()
+            .is_some_and(|store| len > store.threshold)
+    }
+
+    fn blob_dir(&self) -> io::Result<&Path> {
+        self.blobs
+            .as_ref()
+            .map(|store| store.dir.as_path())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "blob storage is not configured")
+            })
+    }
+
+    pub fn read_blob(&self, content_hash: &str) -> io::Result<Vec<u8>> {
+        fs::read(blob_path(self.blob_dir()?, content_hash))
+    }
+
+    pub fn remove_blob(&self, content_hash: &str) -> io::Result<()> {
+        match fs::remove_file(blob_path(self.blob_dir()?, content_hash)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Lists the names of the blob files in the blob directory, leaving out the
+    /// temporary files of writes in progress.
+    pub fn list_blobs(&self) -> io::Result<Vec<String>> {
+        let Ok(dir) = self.blob_dir() else {
+            return Ok(vec![]);
+        };
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut blobs = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_file() && !name.starts_with('.') && !name.ends_with(".tmp") {
+                blobs.push(name);
+            }
+        }
+
+        Ok(blobs)
+    }
+}
+
+/// Blobs written by a transaction that has not committed yet. They wait under
+/// a temporary name until `publish` moves them into place, after the commit.
+/// Blobs still staged when dropped, as after a rollback, are deleted.
+pub struct StagedBlobs<'s> {
+    store: &'s ContentStore,
+    staged: Vec<(PathBuf, PathBuf)>,
+}
+
+impl<'s> StagedBlobs<'s> {
+    pub fn new(store: &'s ContentStore) -> Self {
+        Self {
+            store,
+            staged: vec![],
+        }
+    }
+
+    pub fn store(&self) -> &ContentStore {
+        self.store
+    }
+
+    pub fn stage(&mut self, content_hash: &str, stored: &[u8]) -> io::Result<()> {
+        let dir = self.store.blob_dir()?;
+        fs::create_dir_all(dir)?;
+
+        let temp_path = dir.join(format!(".{}.{}.tmp", content_hash, uuid::Uuid::new_v4()));
+        let result = fs::File::create(&temp_path).and_then(|mut file| {
+            file.write_all(stored)?;
+            file.sync_all()
+        });
+
+        match result {
+            Ok(()) => {
+                self.staged.push((temp_path, blob_path(dir, content_hash)));
+                Ok(())
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path);
+                Err(err)
+            }
+        }
+    }
+
+    /// Moves staged blobs into place, so readers never see a partial body.
+    pub fn publish(&mut self) -> io::Result<()> {
+        while let Some((temp_path, path)) = self.staged.pop() {
+            if let Err(err) = fs::rename(&temp_path, path) {
+                let _ = fs::remove_file(temp_path);
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for StagedBlobs<'_> {
+    fn drop(&mut self) {
+        for (temp_path, _) in self.staged.drain(..) {
+            let _ = fs::remove_file(temp_path);
+        }
+    }
+}
+
+pub fn blob_path(dir: &Path, content_hash: &str) -> PathBuf {
+    dir.join(content_hash)
+}
+
+/// Returns the key a clip body or attachment is stored under in the `contents` table.
+pub fn hash<C: AsRef<[u8]>>(content: C) -> String {
+    hex::encode(Sha256::digest(content.as_ref()))
+}
+
+pub fn decode_bytes(stored: Vec<u8>, compressed: bool) -> io::Result<Vec<u8>> {
+    if !compressed {
+        return Ok(stored);
+    }
+
+    let mut bytes = Vec::new();
+    DeflateDecoder::new(stored.as_slice()).read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+pub fn decode(stored: Vec<u8>, compressed: bool) -> io::Result<String> {
+    String::from_utf8(decode_bytes(stored, compressed)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -1,3 +1,4 @@
+pub mod content;
 pub mod model;
 pub mod query;
 
@@ -11,6 +12,12 @@ use uuid::Uuid;
 pub enum DataError {
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("content encoding error: {0}")]
+    Content(#[from] std::io::Error),
+    #[error("clip was modified by another request")]
+    VersionMismatch,
+    #[error("clip {0} does not exist")]
+    MissingClip(String),
 }
 
 pub type AppDatabase = Database<Sqlite>;
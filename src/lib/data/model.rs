@@ -1,19 +1,33 @@
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDate, NaiveDateTime, Utc};
 use std::convert::TryFrom;
 
-use crate::data::DbId;
+use crate::data::{content, DbId};
+use crate::domain::collection::Member;
+use crate::domain::webhook::{Event, WebhookError};
 use crate::{ClipError, ShortCode, Time};
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct Clip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) short_code: String,
-    pub(in crate::data) content: String,
+    pub(in crate::data) content_hash: String,
+    pub(in crate::data) content: Vec<u8>,
+    pub(in crate::data) compressed: bool,
+    pub(in crate::data) on_disk: bool,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) posted_at: NaiveDateTime,
     pub(in crate::data) expires_at: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) hits: i64,
+    pub(in crate::data) version: i64,
+    pub(in crate::data) tags: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) detected_language: Option<String>,
+    pub(in crate::data) forked_from: Option<String>,
+    pub(in crate::data) forks: Option<String>,
+    pub(in crate::data) attachment_name: Option<String>,
+    pub(in crate::data) attachment_type: Option<String>,
+    pub(in crate::data) attachment_size: Option<i64>,
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -23,15 +37,165 @@ impl TryFrom<Clip> for crate::domain::Clip {
         use crate::domain::clip::field;
         use std::str::FromStr;
 
+        let attachment = match (
+            clip.attachment_name,
+            clip.attachment_type,
+            clip.attachment_size,
+        ) {
+            (Some(name), Some(content_type), Some(size)) => Some(field::Attachment::new(
+                name,
+                content_type,
+                u64::try_from(size)?,
+            )),
+            _ => None,
+        };
+
+        let content = field::Content::stored(content::decode(clip.content, clip.compressed)?);
+
         Ok(Self {
             clip_id: field::ClipId::new(DbId::from_str(clip.clip_id.as_str())?),
             short_code: field::ShortCode::from(clip.short_code),
-            content: field::Content::new(clip.content.as_str())?,
-            title: field::Title::new(clip.title),
+            content,
+            title: field::Title::stored(clip.title),
             posted_at: field::PostedAt::new(Time::form_naive_utc(clip.posted_at)),
             expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::form_naive_utc)),
             password: field::Password::new(clip.password.unwrap_or_default())?,
             hits: field::Hits::new(u64::try_from(clip.hits)?),
+            version: field::Version::new(u64::try_from(clip.version)?),
+            tags: stored_tags(clip.tags),
+            language: field::Language::stored(clip.language),
+            detected_language: field::Language::stored(clip.detected_language),
+            forked_from: clip.forked_from.map(field::ShortCode::from),
+            forks: stored_forks(clip.forks),
+            attachment,
+        })
+    }
+}
+
+/// Guesses the language of content once, when it is saved.
+fn detect_language(content: &str) -> Option<String> {
+    crate::domain::clip::field::Language::detect(content).into_inner()
+}
+
+/// Tags are loaded comma separated, in no particular order.
+fn stored_tags(tags: Option<String>) -> crate::domain::clip::field::Tags {
+    let mut tags: Vec<String> = tags
+        .unwrap_or_default()
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_owned)
+        .collect();
+    tags.sort();
+
+    crate::domain::clip::field::Tags::stored(tags)
+}
+
+/// Forks are loaded comma separated, like tags.
+fn stored_forks(forks: Option<String>) -> Vec<ShortCode> {
+    let mut forks: Vec<ShortCode> = forks
+        .unwrap_or_default()
+        .split(',')
+        .filter(|short_code| !short_code.is_empty())
+        .map(ShortCode::from)
+        .collect();
+    forks.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+    forks
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ClipSummary {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) posted_at: NaiveDateTime,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) protected: bool,
+    pub(in crate::data) hits: i64,
+    pub(in crate::data) tags: Option<String>,
+}
+
+impl TryFrom<ClipSummary> for crate::domain::clip::ClipSummary {
+    type Error = ClipError;
+
+    fn try_from(clip: ClipSummary) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+
+        // Titles of protected clips stay as private as their content.
+        let title = if clip.protected { None } else { clip.title };
+
+        Ok(Self {
+            short_code: field::ShortCode::from(clip.short_code),
+            title: field::Title::stored(title),
+            posted_at: field::PostedAt::new(Time::form_naive_utc(clip.posted_at)),
+            expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::form_naive_utc)),
+            protected: clip.protected,
+            hits: field::Hits::new(u64::try_from(clip.hits)?),
+            tags: stored_tags(clip.tags),
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct TrashedClip {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) deleted_at: NaiveDateTime,
+    pub(in crate::data) protected: bool,
+}
+
+impl From<TrashedClip> for crate::domain::clip::TrashedClip {
+    fn from(clip: TrashedClip) -> Self {
+        use crate::domain::clip::field;
+
+        let (title, expires_at) = match clip.protected {
+            true => (None, None),
+            false => (clip.title, clip.expires_at),
+        };
+
+        Self {
+            short_code: field::ShortCode::from(clip.short_code),
+            title: field::Title::stored(title),
+            expires_at: field::ExpiresAt::new(expires_at.map(Time::form_naive_utc)),
+            deleted_at: Time::form_naive_utc(clip.deleted_at),
+            protected: clip.protected,
+        }
+    }
+}
+
+pub struct NewViews {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) day: NaiveDate,
+    pub(in crate::data) count: u32,
+    pub(in crate::data) visitors: Vec<String>,
+}
+
+impl From<crate::service::ask::NewViews> for NewViews {
+    fn from(req: crate::service::ask::NewViews) -> Self {
+        Self {
+            short_code: req.short_code.into_inner(),
+            day: req.day,
+            count: req.count,
+            visitors: req.visitors,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct DailyViews {
+    pub(in crate::data) day: NaiveDate,
+    pub(in crate::data) views: i64,
+    pub(in crate::data) unique_views: i64,
+}
+
+impl TryFrom<DailyViews> for crate::domain::clip::stats::DailyViews {
+    type Error = ClipError;
+
+    fn try_from(views: DailyViews) -> Result<Self, Self::Error> {
+        Ok(Self {
+            day: views.day,
+            views: u64::try_from(views.views)?,
+            unique_views: u64::try_from(views.unique_views)?,
         })
     }
 }
@@ -65,43 +229,329 @@ impl From<String> for GetClip {
 pub struct NewClip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) short_code: String,
+    pub(in crate::data) content_hash: String,
     pub(in crate::data) content: String,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) posted_at: i64,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) tags: Vec<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) detected_language: Option<String>,
+    pub(in crate::data) forked_from: Option<String>,
 }
 
 impl From<crate::service::ask::NewClip> for NewClip {
     fn from(req: crate::service::ask::NewClip) -> Self {
+        let content = req.content.into_inner();
+
         Self {
             clip_id: DbId::new().into(),
-            content: req.content.into_inner(),
+            content_hash: content::hash(&content),
+            detected_language: detect_language(&content),
+            content,
             title: req.title.into_inner(),
             expires_at: req.expires_at.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
+            tags: req.tags.into_inner(),
+            language: req.language.into_inner(),
+            forked_from: None,
             short_code: ShortCode::default().into(),
             posted_at: Utc::now().timestamp(),
         }
     }
 }
 
+impl From<crate::service::ask::NewFork> for NewClip {
+    fn from(req: crate::service::ask::NewFork) -> Self {
+        Self {
+            forked_from: Some(req.forked_from.into_inner()),
+            ..Self::from(req.clip)
+        }
+    }
+}
+
+pub struct NewAttachment {
+    pub(in crate::data) content_hash: String,
+    pub(in crate::data) file_name: String,
+    pub(in crate::data) content_type: String,
+    pub(in crate::data) content: Vec<u8>,
+}
+
+impl From<crate::service::ask::NewAttachment> for NewAttachment {
+    fn from(req: crate::service::ask::NewAttachment) -> Self {
+        Self {
+            content_hash: content::hash(&req.content),
+            file_name: req.file_name,
+            content_type: req.content_type,
+            content: req.content,
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Attachment {
+    pub(in crate::data) file_name: String,
+    pub(in crate::data) content_type: String,
+    pub(in crate::data) content_hash: String,
+    pub(in crate::data) content: Vec<u8>,
+    pub(in crate::data) compressed: bool,
+    pub(in crate::data) on_disk: bool,
+}
+
+impl TryFrom<Attachment> for (crate::domain::clip::field::Attachment, Vec<u8>) {
+    type Error = ClipError;
+
+    fn try_from(attachment: Attachment) -> Result<Self, Self::Error> {
+        let content = content::decode_bytes(attachment.content, attachment.compressed)?;
+        let size = u64::try_from(content.len())?;
+
+        Ok((
+            crate::domain::clip::field::Attachment::new(
+                attachment.file_name,
+                attachment.content_type,
+                size,
+            ),
+            content,
+        ))
+    }
+}
+
 pub struct UpdateClip {
     pub(in crate::data) short_code: String,
+    pub(in crate::data) content_hash: String,
     pub(in crate::data) content: String,
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) expires_at: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) language: Option<String>,
+    pub(in crate::data) detected_language: Option<String>,
 }
 
 impl From<crate::service::ask::UpdateClip> for UpdateClip {
     fn from(req: crate::service::ask::UpdateClip) -> Self {
+        let content = req.content.into_inner();
+
         Self {
-            content: req.content.into_inner(),
+            content_hash: content::hash(&content),
+            detected_language: detect_language(&content),
+            content,
             title: req.title.into_inner(),
             expires_at: req.expires_at.into_inner().map(|time| time.timestamp()),
             password: req.password.into_inner(),
+            language: req.language.into_inner(),
             short_code: req.short_code.into_inner(),
         }
     }
 }
+
+pub struct PatchClip {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) content_hash: Option<String>,
+    pub(in crate::data) content: Option<String>,
+    pub(in crate::data) title: Option<Option<String>>,
+    pub(in crate::data) expires_at: Option<Option<i64>>,
+    pub(in crate::data) password: Option<Option<String>>,
+    pub(in crate::data) language: Option<Option<String>>,
+    pub(in crate::data) detected_language: Option<Option<String>>,
+}
+
+impl From<(ShortCode, crate::service::ask::PatchClip)> for PatchClip {
+    fn from((short_code, req): (ShortCode, crate::service::ask::PatchClip)) -> Self {
+        let content = req
+            .content
+            .into_change(|content| Some(content.into_inner()))
+            .flatten();
+
+        Self {
+            short_code: short_code.into_inner(),
+            content_hash: content.as_ref().map(content::hash),
+            detected_language: content.as_deref().map(detect_language),
+            content,
+            title: req.title.into_change(|title| title.into_inner()),
+            expires_at: req
+                .expires_at
+                .into_change(|expires_at| expires_at.into_inner().map(|time| time.timestamp())),
+            password: req.password.into_change(|password| password.into_inner()),
+            language: req.language.into_change(|language| language.into_inner()),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Collection {
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) password: Option<String>,
+    pub(in crate::data) posted_at: NaiveDateTime,
+}
+
+impl TryFrom<(Collection, Vec<Member>)> for crate::domain::collection::Collection {
+    type Error = ClipError;
+
+    fn try_from((collection, clips): (Collection, Vec<Member>)) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+
+        Ok(Self {
+            short_code: ShortCode::from(collection.short_code),
+            title: field::Title::stored(collection.title),
+            posted_at: field::PostedAt::new(Time::form_naive_utc(collection.posted_at)),
+            password: field::Password::new(collection.password.unwrap_or_default())?,
+            clips,
+        })
+    }
+}
+
+pub struct NewCollection {
+    pub(in crate::data) collection_id: String,
+    pub(in crate::data) short_code: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) password: Option<String>,
+    pub(in crate::data) posted_at: i64,
+    pub(in crate::data) clips: Vec<String>,
+}
+
+impl From<crate::service::ask::NewCollection> for NewCollection {
+    fn from(req: crate::service::ask::NewCollection) -> Self {
+        Self {
+            collection_id: DbId::new().into(),
+            short_code: ShortCode::default().into(),
+            title: req.title.into_inner(),
+            password: req.password.into_inner(),
+            posted_at: Utc::now().timestamp(),
+            clips: req.clips.into_iter().map(ShortCode::into_inner).collect(),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Webhook {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) secret: String,
+    pub(in crate::data) events: String,
+    pub(in crate::data) created_at: NaiveDateTime,
+}
+
+impl TryFrom<Webhook> for crate::domain::webhook::Webhook {
+    type Error = WebhookError;
+
+    fn try_from(webhook: Webhook) -> Result<Self, Self::Error> {
+        Ok(Self {
+            webhook_id: webhook.webhook_id,
+            url: webhook.url,
+            events: parse_events(&webhook.events)?,
+            created_at: Time::form_naive_utc(webhook.created_at),
+            secret: Some(webhook.secret),
+        })
+    }
+}
+
+/// Events are stored comma separated, with `*` standing for every event.
+fn parse_events(events: &str) -> Result<Vec<Event>, WebhookError> {
+    match events {
+        "*" => Ok(vec![]),
+        events => events
+            .split(',')
+            .map(|event| {
+                event
+                    .parse()
+                    .map_err(|_| WebhookError::UnknownEvent(event.to_owned()))
+            })
+            .collect(),
+    }
+}
+
+pub struct NewWebhook {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) secret: String,
+    pub(in crate::data) events: String,
+    pub(in crate::data) created_at: i64,
+}
+
+impl From<crate::service::ask::NewWebhook> for NewWebhook {
+    fn from(req: crate::service::ask::NewWebhook) -> Self {
+        let secret = match req.secret {
+            Some(secret) if !secret.is_empty() => secret,
+            _ => hex::encode(rand::random::<[u8; 32]>()),
+        };
+        let events = match req.events.is_empty() {
+            true => "*".to_owned(),
+            false => req
+                .events
+                .iter()
+                .map(Event::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+
+        Self {
+            webhook_id: DbId::new().into(),
+            url: req.url,
+            secret,
+            events,
+            created_at: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct Delivery {
+    pub(in crate::data) delivery_id: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) secret: String,
+    pub(in crate::data) event: String,
+    pub(in crate::data) payload: String,
+    pub(in crate::data) attempts: i64,
+}
+
+impl TryFrom<Delivery> for crate::domain::webhook::Delivery {
+    type Error = WebhookError;
+
+    fn try_from(delivery: Delivery) -> Result<Self, Self::Error> {
+        Ok(Self {
+            event: delivery
+                .event
+                .parse()
+                .map_err(|_| WebhookError::UnknownEvent(delivery.event.clone()))?,
+            delivery_id: delivery.delivery_id,
+            url: delivery.url,
+            secret: delivery.secret,
+            payload: delivery.payload,
+            attempts: u32::try_from(delivery.attempts).unwrap_or(u32::MAX),
+        })
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct DeadLetter {
+    pub(in crate::data) delivery_id: String,
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) event: String,
+    pub(in crate::data) payload: String,
+    pub(in crate::data) attempts: i64,
+    pub(in crate::data) last_error: Option<String>,
+    pub(in crate::data) failed_at: NaiveDateTime,
+}
+
+impl TryFrom<DeadLetter> for crate::domain::webhook::DeadLetter {
+    type Error = WebhookError;
+
+    fn try_from(letter: DeadLetter) -> Result<Self, Self::Error> {
+        Ok(Self {
+            event: letter
+                .event
+                .parse()
+                .map_err(|_| WebhookError::UnknownEvent(letter.event.clone()))?,
+            delivery_id: letter.delivery_id,
+            webhook_id: letter.webhook_id,
+            url: letter.url,
+            payload: letter.payload,
+            attempts: u32::try_from(letter.attempts).unwrap_or(u32::MAX),
+            last_error: letter.last_error,
+            failed_at: Time::form_naive_utc(letter.failed_at),
+        })
+    }
+}
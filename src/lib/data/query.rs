@@ -1,108 +1,616 @@
+use chrono::NaiveDate;
 use sqlx::Row;
 
-use crate::data::{DataError, DatabasePool};
+use crate::data::content::{self, ContentStore, StagedBlobs};
+use crate::data::{DataError, DatabasePool, Transaction};
 use crate::web::api::ApiKey;
-use crate::ShortCode;
 
 use super::model;
 
 type Result<T> = std::result::Result<T, DataError>;
 
-pub async fn increase_hit_count(
-    short_code: &ShortCode,
-    hits: u32,
+/// Adds views to several clips, counting them towards the day they were made
+/// on. A visitor is only counted once per clip and day. Returns the new hit
+/// counts in the same order, with `None` for clips that no longer exist.
+pub async fn increase_hit_counts<M: Into<model::NewViews>>(
+    views: Vec<M>,
+    transaction: &mut Transaction<'_>,
+) -> Result<Vec<Option<i64>>> {
+    let mut totals = Vec::with_capacity(views.len());
+    for views in views {
+        let views = views.into();
+        let total = sqlx::query_scalar!(
+            r#"UPDATE clips SET hits = hits + ?
+               WHERE short_code = ? AND deleted_at IS NULL
+               RETURNING hits"#,
+            views.count,
+            views.short_code
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        if total.is_some() {
+            let mut unique_views: i64 = 0;
+            for visitor in &views.visitors {
+                let inserted = sqlx::query!(
+                    r#"INSERT INTO clip_visitors (clip_id, day, visitor)
+                       SELECT clip_id, ?, ? FROM clips WHERE short_code = ?
+                       ON CONFLICT DO NOTHING"#,
+                    views.day,
+                    visitor,
+                    views.short_code
+                )
+                .execute(&mut **transaction)
+                .await?
+                .rows_affected();
+                unique_views += inserted as i64;
+            }
+
+            sqlx::query!(
+                r#"INSERT INTO clip_views (clip_id, day, views, unique_views)
+                   SELECT clip_id, ?, ?, ? FROM clips WHERE short_code = ?
+                   ON CONFLICT (clip_id, day) DO UPDATE SET
+                    views = views + excluded.views,
+                    unique_views = unique_views + excluded.unique_views"#,
+                views.day,
+                views.count,
+                unique_views,
+                views.short_code
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        totals.push(total);
+    }
+
+    Ok(totals)
+}
+
+/// Forgets who viewed clips before `day`. Their unique views stay counted.
+pub async fn delete_visitors_before(day: NaiveDate, pool: &DatabasePool) -> Result<u64> {
+    Ok(sqlx::query!("DELETE FROM clip_visitors WHERE day < ?", day)
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+/// The secret stored under `name`, storing `secret` first if there is none.
+pub async fn get_or_insert_secret(
+    name: &str,
+    secret: &[u8],
     pool: &DatabasePool,
-) -> Result<()> {
-    let short_code = short_code.as_str();
+) -> Result<Vec<u8>> {
+    Ok(sqlx::query_scalar!(
+        r#"INSERT INTO server_secrets (name, secret) VALUES (?, ?)
+           ON CONFLICT (name) DO UPDATE SET secret = secret
+           RETURNING secret"#,
+        name,
+        secret
+    )
+    .fetch_one(pool)
+    .await?)
+}
 
-    Ok(sqlx::query!(
-        "UPDATE clips SET hits = hits + ? WHERE short_code = ?",
-        hits,
-        short_code
+/// Views of a clip per day, from `since` on. Days without views are left out.
+pub async fn get_clip_views(
+    short_code: &str,
+    since: NaiveDate,
+    pool: &DatabasePool,
+) -> Result<Vec<model::DailyViews>> {
+    Ok(sqlx::query_as!(
+        model::DailyViews,
+        r#"SELECT clip_views.day, clip_views.views, clip_views.unique_views
+           FROM clip_views
+           INNER JOIN clips ON clips.clip_id = clip_views.clip_id
+           WHERE clips.short_code = ? AND clip_views.day >= ?
+           ORDER BY clip_views.day"#,
+        short_code,
+        since
     )
-    .execute(pool)
-    .await
-    .map(|_| ())?)
+    .fetch_all(pool)
+    .await?)
 }
 
 pub async fn get_clip<M: Into<model::GetClip>>(
     model: M,
+    store: &ContentStore,
     pool: &DatabasePool,
 ) -> Result<model::Clip> {
     let model = model.into();
     let short_code = model.short_code.as_str();
 
-    Ok(sqlx::query_as!(
+    let mut clip = sqlx::query_as!(
         model::Clip,
-        "SELECT * FROM clips WHERE short_code = ?",
+        r#"SELECT
+            clips.clip_id,
+            clips.short_code,
+            contents.content_hash,
+            contents.content,
+            contents.compressed,
+            contents.on_disk,
+            clips.title,
+            clips.posted_at,
+            clips.expires_at,
+            clips.password,
+            clips.hits,
+            clips.version,
+            (SELECT group_concat(tags.name)
+             FROM clip_tags
+             INNER JOIN tags ON tags.tag_id = clip_tags.tag_id
+             WHERE clip_tags.clip_id = clips.clip_id) AS "tags?: String",
+            clips.language,
+            clips.detected_language,
+            clips.forked_from,
+            (SELECT group_concat(forks.short_code)
+             FROM clips AS forks
+             WHERE forks.forked_from = clips.short_code
+               AND forks.password IS NULL
+               AND forks.deleted_at IS NULL) AS "forks?: String",
+            attachments.file_name AS "attachment_name?",
+            attachments.content_type AS "attachment_type?",
+            attachments.size AS "attachment_size?"
+           FROM clips
+           INNER JOIN contents ON contents.content_hash = clips.content_hash
+           LEFT JOIN attachments ON attachments.clip_id = clips.clip_id
+           WHERE clips.short_code = ? AND clips.deleted_at IS NULL"#,
         short_code
     )
     .fetch_one(pool)
+    .await?;
+
+    if clip.on_disk {
+        clip.content = store.read_blob(&clip.content_hash)?;
+    }
+
+    Ok(clip)
+}
+
+pub async fn get_attachment<M: Into<model::GetClip>>(
+    model: M,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<model::Attachment> {
+    let model = model.into();
+    let short_code = model.short_code.as_str();
+
+    let mut attachment = sqlx::query_as!(
+        model::Attachment,
+        r#"SELECT
+            attachments.file_name,
+            attachments.content_type,
+            contents.content_hash,
+            contents.content,
+            contents.compressed,
+            contents.on_disk
+           FROM attachments
+           INNER JOIN clips ON clips.clip_id = attachments.clip_id
+           INNER JOIN contents ON contents.content_hash = attachments.content_hash
+           WHERE clips.short_code = ? AND clips.deleted_at IS NULL"#,
+        short_code
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if attachment.on_disk {
+        attachment.content = store.read_blob(&attachment.content_hash)?;
+    }
+
+    Ok(attachment)
+}
+
+/// Lists the newest unexpired clips, optionally only those carrying `tag`.
+pub async fn list_clips(
+    tag: Option<&str>,
+    limit: u32,
+    pool: &DatabasePool,
+) -> Result<Vec<model::ClipSummary>> {
+    Ok(sqlx::query_as!(
+        model::ClipSummary,
+        r#"SELECT
+            clips.short_code,
+            clips.title,
+            clips.posted_at,
+            clips.expires_at,
+            clips.password IS NOT NULL AS "protected!: bool",
+            clips.hits,
+            (SELECT group_concat(tags.name)
+             FROM clip_tags
+             INNER JOIN tags ON tags.tag_id = clip_tags.tag_id
+             WHERE clip_tags.clip_id = clips.clip_id) AS "tags?: String"
+           FROM clips
+           WHERE (clips.expires_at IS NULL OR clips.expires_at >= strftime('%s', 'now'))
+             AND clips.deleted_at IS NULL
+             AND (?1 IS NULL OR EXISTS (
+                SELECT 1 FROM clip_tags
+                INNER JOIN tags ON tags.tag_id = clip_tags.tag_id
+                WHERE clip_tags.clip_id = clips.clip_id AND tags.name = ?1))
+           ORDER BY clips.posted_at DESC
+           LIMIT ?2"#,
+        tag,
+        limit
+    )
+    .fetch_all(pool)
     .await?)
 }
 
+/// Takes a reference to a body, storing it if it is new. A new blob is only
+/// staged; it is published once the transaction has committed.
+async fn acquire_content(
+    content_hash: &str,
+    content: &[u8],
+    blobs: &mut StagedBlobs<'_>,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let referenced = sqlx::query!(
+        "UPDATE contents SET ref_count = ref_count + 1 WHERE content_hash = ?",
+        content_hash
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+
+    if referenced > 0 {
+        return Ok(());
+    }
+
+    let (mut stored, compressed) = blobs.store().encode(content)?;
+    let on_disk = blobs.store().is_blob(stored.len());
+
+    if on_disk {
+        blobs.stage(content_hash, &stored)?;
+        stored.clear();
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO contents (content_hash, content, compressed, on_disk, ref_count)
+           VALUES (?, ?, ?, ?, 1)
+           ON CONFLICT (content_hash) DO UPDATE SET ref_count = ref_count + 1"#,
+        content_hash,
+        stored,
+        compressed,
+        on_disk,
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
+async fn release_content(content_hash: &str, transaction: &mut Transaction<'_>) -> Result<()> {
+    sqlx::query!(
+        "UPDATE contents SET ref_count = ref_count - 1 WHERE content_hash = ?",
+        content_hash
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn new_clip<M: Into<model::NewClip>>(
     model: M,
+    store: &ContentStore,
     pool: &DatabasePool,
 ) -> Result<model::Clip> {
-    let model = model.into();
-    let _ = sqlx::query!(
+    insert_clip(model.into(), None, store, pool).await
+}
+
+pub async fn new_clip_with_attachment<M, A>(
+    model: M,
+    attachment: A,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<model::Clip>
+where
+    M: Into<model::NewClip>,
+    A: Into<model::NewAttachment>,
+{
+    insert_clip(model.into(), Some(attachment.into()), store, pool).await
+}
+
+/// Inserts every clip or, if any of them fails, none of them. Returns their
+/// short codes in order.
+pub async fn new_clips<M: Into<model::NewClip>>(
+    models: Vec<M>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Vec<String>> {
+    let mut blobs = StagedBlobs::new(store);
+    let mut transaction = pool.begin().await?;
+    let mut short_codes = Vec::with_capacity(models.len());
+    for model in models {
+        let model = model.into();
+        short_codes.push(model.short_code.clone());
+        insert_clip_in(model, None, &mut blobs, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    blobs.publish()?;
+
+    Ok(short_codes)
+}
+
+async fn insert_clip(
+    model: model::NewClip,
+    attachment: Option<model::NewAttachment>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let mut blobs = StagedBlobs::new(store);
+    let mut transaction = pool.begin().await?;
+    let short_code = model.short_code.clone();
+    insert_clip_in(model, attachment, &mut blobs, &mut transaction).await?;
+    transaction.commit().await?;
+    blobs.publish()?;
+
+    get_clip(short_code, store, pool).await
+}
+
+async fn insert_clip_in(
+    model: model::NewClip,
+    attachment: Option<model::NewAttachment>,
+    blobs: &mut StagedBlobs<'_>,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    acquire_content(
+        &model.content_hash,
+        model.content.as_bytes(),
+        blobs,
+        transaction,
+    )
+    .await?;
+
+    sqlx::query!(
         r#"INSERT INTO clips (
             clip_id, 
             short_code, 
-            content, 
+            content_hash, 
             title, 
             posted_at, 
             expires_at, 
             password, 
-            hits) 
-           VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            hits,
+            language,
+            detected_language,
+            forked_from) 
+           VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         model.clip_id,
         model.short_code,
-        model.content,
+        model.content_hash,
         model.title,
         model.posted_at,
         model.expires_at,
         model.password,
         0,
+        model.language,
+        model.detected_language,
+        model.forked_from,
     )
-    .execute(pool)
+    .execute(&mut **transaction)
     .await?;
 
-    get_clip(model.short_code, pool).await
+    for tag in &model.tags {
+        sqlx::query!(
+            "INSERT INTO tags (name) VALUES (?) ON CONFLICT (name) DO NOTHING",
+            tag
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"INSERT INTO clip_tags (clip_id, tag_id)
+               SELECT ?, tag_id FROM tags WHERE name = ?"#,
+            model.clip_id,
+            tag
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    if let Some(attachment) = attachment {
+        let size = attachment.content.len() as i64;
+
+        acquire_content(
+            &attachment.content_hash,
+            &attachment.content,
+            blobs,
+            transaction,
+        )
+        .await?;
+
+        sqlx::query!(
+            r#"INSERT INTO attachments (clip_id, file_name, content_type, size, content_hash)
+               VALUES (?, ?, ?, ?, ?)"#,
+            model.clip_id,
+            attachment.file_name,
+            attachment.content_type,
+            size,
+            attachment.content_hash,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    // a fork shares the attachment of its original, like identical bodies
+    if let Some(forked_from) = &model.forked_from {
+        let content_hash = sqlx::query_scalar!(
+            r#"INSERT INTO attachments (clip_id, file_name, content_type, size, content_hash)
+               SELECT ?, file_name, content_type, size, content_hash FROM attachments
+               WHERE clip_id = (SELECT clip_id FROM clips WHERE short_code = ?)
+               RETURNING content_hash"#,
+            model.clip_id,
+            forked_from,
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        if let Some(content_hash) = content_hash {
+            sqlx::query!(
+                "UPDATE contents SET ref_count = ref_count + 1 WHERE content_hash = ?",
+                content_hash
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bumps the version of a clip as the first write of a transaction, so the check
+/// against `if_match` holds until commit.
+async fn bump_version(
+    short_code: &str,
+    if_match: Option<&[i64]>,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let version = sqlx::query_scalar!(
+        r#"UPDATE clips SET version = version + 1
+           WHERE short_code = ? AND deleted_at IS NULL
+           RETURNING version"#,
+        short_code
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    match if_match {
+        Some(versions) if !versions.contains(&(version - 1)) => Err(DataError::VersionMismatch),
+        _ => Ok(()),
+    }
 }
 
 pub async fn update_clip<M: Into<model::UpdateClip>>(
     model: M,
+    if_match: Option<&[i64]>,
+    store: &ContentStore,
     pool: &DatabasePool,
 ) -> Result<model::Clip> {
     let model = model.into();
-    let _ = sqlx::query!(
+    let mut blobs = StagedBlobs::new(store);
+    let mut transaction = pool.begin().await?;
+    bump_version(&model.short_code, if_match, &mut transaction).await?;
+
+    let previous_hash = sqlx::query_scalar!(
+        "SELECT content_hash FROM clips WHERE short_code = ?",
+        model.short_code
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    acquire_content(
+        &model.content_hash,
+        model.content.as_bytes(),
+        &mut blobs,
+        &mut transaction,
+    )
+    .await?;
+
+    sqlx::query!(
         r#"UPDATE clips SET
-            content = ?, 
+            content_hash = ?, 
             title = ?, 
             expires_at = ?, 
-            password = ?
+            password = ?,
+            language = ?,
+            detected_language = ?
            WHERE short_code = ?"#,
-        model.content,
+        model.content_hash,
         model.title,
         model.expires_at,
         model.password,
+        model.language,
+        model.detected_language,
         model.short_code,
     )
-    .execute(pool)
+    .execute(&mut *transaction)
+    .await?;
+
+    release_content(&previous_hash, &mut transaction).await?;
+    transaction.commit().await?;
+    blobs.publish()?;
+
+    get_clip(model.short_code, store, pool).await
+}
+
+/// Applies only the changed fields of a clip, in a single transaction.
+pub async fn patch_clip<M: Into<model::PatchClip>>(
+    model: M,
+    if_match: Option<&[i64]>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let model = model.into();
+    let mut blobs = StagedBlobs::new(store);
+    let mut transaction = pool.begin().await?;
+    bump_version(&model.short_code, if_match, &mut transaction).await?;
+
+    let previous_hash = sqlx::query_scalar!(
+        "SELECT content_hash FROM clips WHERE short_code = ?",
+        model.short_code
+    )
+    .fetch_one(&mut *transaction)
     .await?;
 
-    get_clip(model.short_code, pool).await
+    if let (Some(content_hash), Some(content)) = (&model.content_hash, &model.content) {
+        acquire_content(
+            content_hash,
+            content.as_bytes(),
+            &mut blobs,
+            &mut transaction,
+        )
+        .await?;
+    }
+
+    let set_title = model.title.is_some();
+    let title = model.title.flatten();
+    let set_expires_at = model.expires_at.is_some();
+    let expires_at = model.expires_at.flatten();
+    let set_password = model.password.is_some();
+    let password = model.password.flatten();
+    let set_language = model.language.is_some();
+    let language = model.language.flatten();
+    let set_detected_language = model.detected_language.is_some();
+    let detected_language = model.detected_language.flatten();
+
+    sqlx::query!(
+        r#"UPDATE clips SET
+            content_hash = COALESCE(?, content_hash),
+            title = CASE WHEN ? THEN ? ELSE title END,
+            expires_at = CASE WHEN ? THEN ? ELSE expires_at END,
+            password = CASE WHEN ? THEN ? ELSE password END,
+            language = CASE WHEN ? THEN ? ELSE language END,
+            detected_language = CASE WHEN ? THEN ? ELSE detected_language END
+           WHERE short_code = ?"#,
+        model.content_hash,
+        set_title,
+        title,
+        set_expires_at,
+        expires_at,
+        set_password,
+        password,
+        set_language,
+        language,
+        set_detected_language,
+        detected_language,
+        model.short_code,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    if model.content_hash.is_some() {
+        release_content(&previous_hash, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+    blobs.publish()?;
+
+    get_clip(model.short_code, store, pool).await
 }
 
 pub async fn save_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<ApiKey> {
     let bytes = api_key.clone().into_inner();
 
-    let _ = sqlx::query!("INSERT INTO api_keys (api_key) VALUES (?)", bytes)
+    sqlx::query!("INSERT INTO api_keys (api_key) VALUES (?)", bytes)
         .execute(pool)
-        .await
-        .map(|_| ())?;
+        .await?;
 
     Ok(api_key)
 }
@@ -141,31 +649,648 @@ pub async fn is_valid_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<bo
     )
 }
 
-pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
-    Ok(
-        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires_at"#)
+/// Moves expired clips to the trash, returning their short codes.
+pub async fn trash_expired(pool: &DatabasePool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar!(
+        r#"UPDATE clips SET deleted_at = strftime('%s', 'now')
+           WHERE deleted_at IS NULL AND strftime('%s', 'now') > expires_at
+           RETURNING short_code"#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Trashed clips, most recently deleted first.
+pub async fn list_trash(pool: &DatabasePool) -> Result<Vec<model::TrashedClip>> {
+    Ok(sqlx::query_as!(
+        model::TrashedClip,
+        r#"SELECT
+            short_code,
+            title,
+            expires_at,
+            deleted_at AS "deleted_at!",
+            password IS NOT NULL AS "protected!: bool"
+           FROM clips
+           WHERE deleted_at IS NOT NULL
+           ORDER BY deleted_at DESC"#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The password of a trashed clip, so it can be checked before restoring.
+pub async fn get_trashed_password(short_code: &str, pool: &DatabasePool) -> Result<Option<String>> {
+    Ok(sqlx::query_scalar!(
+        "SELECT password FROM clips WHERE short_code = ? AND deleted_at IS NOT NULL",
+        short_code
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Takes a clip out of the trash. Its expiry date is cleared, or it would be
+/// trashed again right away.
+pub async fn restore_clip(
+    short_code: &str,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    sqlx::query_scalar!(
+        r#"UPDATE clips SET deleted_at = NULL, expires_at = NULL, version = version + 1
+           WHERE short_code = ? AND deleted_at IS NOT NULL
+           RETURNING short_code"#,
+        short_code
+    )
+    .fetch_one(pool)
+    .await?;
+
+    get_clip(short_code.to_owned(), store, pool).await
+}
+
+/// Deletes clips trashed at or before `cutoff`, a unix timestamp, returning
+/// their short codes.
+pub async fn purge_trash(cutoff: i64, pool: &DatabasePool) -> Result<Vec<String>> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"UPDATE contents SET ref_count = ref_count - (
+            SELECT COUNT(*) FROM clips
+            WHERE clips.content_hash = contents.content_hash
+              AND clips.deleted_at <= ?1
+           ) - (
+            SELECT COUNT(*) FROM attachments
+            INNER JOIN clips ON clips.clip_id = attachments.clip_id
+            WHERE attachments.content_hash = contents.content_hash
+              AND clips.deleted_at <= ?1
+           )
+           WHERE content_hash IN (
+            SELECT content_hash FROM clips WHERE deleted_at <= ?1
+            UNION
+            SELECT attachments.content_hash FROM attachments
+            INNER JOIN clips ON clips.clip_id = attachments.clip_id
+            WHERE clips.deleted_at <= ?1
+           )"#,
+        cutoff
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let purged = sqlx::query_scalar!(
+        "DELETE FROM clips WHERE deleted_at <= ? RETURNING short_code",
+        cutoff
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(purged)
+}
+
+/// Deletes bodies no clip refers to anymore, along with their blob files.
+/// The files are removed before the commit, while the transaction holds the
+/// write lock, so no clip can take a body back in the meantime. A clip that
+/// brings it back later publishes a new file after its own commit.
+pub async fn delete_unreferenced_contents(
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<u64> {
+    let mut transaction = pool.begin().await?;
+
+    let deleted = sqlx::query!(
+        r#"DELETE FROM contents WHERE ref_count <= 0
+           RETURNING content_hash, on_disk AS "on_disk!: bool""#
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    for row in deleted.iter().filter(|row| row.on_disk) {
+        store.remove_blob(&row.content_hash)?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(deleted.len() as u64)
+}
+
+/// Re-encodes every stored body against the current compression threshold,
+/// returning how many rows changed.
+pub async fn recompress_contents(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    let mut blobs = StagedBlobs::new(store);
+    let hashes = sqlx::query_scalar!("SELECT content_hash FROM contents")
+        .fetch_all(pool)
+        .await?;
+    let mut recompressed = 0;
+
+    for content_hash in hashes {
+        let row = sqlx::query!(
+            "SELECT content, compressed, on_disk FROM contents WHERE content_hash = ?",
+            content_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(row) = row else {
+            continue;
+        };
+
+        let stored = match row.on_disk {
+            true => store.read_blob(&content_hash)?,
+            false => row.content,
+        };
+        let (mut stored, compressed) =
+            store.encode(content::decode_bytes(stored, row.compressed)?)?;
+
+        if compressed != row.compressed {
+            if row.on_disk {
+                blobs.stage(&content_hash, &stored)?;
+                stored.clear();
+            }
+
+            sqlx::query!(
+                "UPDATE contents SET content = ?, compressed = ? WHERE content_hash = ?",
+                stored,
+                compressed,
+                content_hash
+            )
             .execute(pool)
+            .await?;
+            blobs.publish()?;
+            recompressed += 1;
+        }
+    }
+
+    Ok(recompressed)
+}
+
+/// Guesses the language of clips saved before guesses were stored, returning
+/// how many got one.
+pub async fn detect_languages(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    use crate::domain::clip::field::Language;
+
+    let short_codes = sqlx::query_scalar!(
+        "SELECT short_code FROM clips WHERE detected_language IS NULL AND deleted_at IS NULL"
+    )
+    .fetch_all(pool)
+    .await?;
+    let mut detected = 0;
+
+    for short_code in short_codes {
+        let clip = get_clip(short_code.clone(), store, pool).await?;
+        let content = content::decode(clip.content, clip.compressed)?;
+        let Some(language) = Language::detect(&content).into_inner() else {
+            continue;
+        };
+
+        sqlx::query!(
+            "UPDATE clips SET detected_language = ? WHERE short_code = ?",
+            language,
+            short_code
+        )
+        .execute(pool)
+        .await?;
+        detected += 1;
+    }
+
+    Ok(detected)
+}
+
+/// Re-keys bodies the `contents` migration stored as `legacy-<clip_id>` by
+/// their hash, merging them with identical bodies. Returns how many were re-keyed.
+pub async fn rehash_legacy_contents(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    let mut blobs = StagedBlobs::new(store);
+    let legacy_hashes =
+        sqlx::query_scalar!("SELECT content_hash FROM contents WHERE content_hash LIKE 'legacy-%'")
+            .fetch_all(pool)
+            .await?;
+    let mut rehashed = 0;
+
+    for legacy_hash in legacy_hashes {
+        let mut transaction = pool.begin().await?;
+        let row = sqlx::query!(
+            "SELECT content, compressed, on_disk, ref_count FROM contents WHERE content_hash = ?",
+            legacy_hash
+        )
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(row) = row else {
+            continue;
+        };
+
+        let stored = match row.on_disk {
+            true => store.read_blob(&legacy_hash)?,
+            false => row.content,
+        };
+        let content_hash = content::hash(content::decode_bytes(stored.clone(), row.compressed)?);
+
+        let merged = sqlx::query!(
+            "UPDATE contents SET ref_count = ref_count + ? WHERE content_hash = ?",
+            row.ref_count,
+            content_hash
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        if merged == 0 {
+            let stored = match row.on_disk {
+                true => {
+                    blobs.stage(&content_hash, &stored)?;
+                    vec![]
+                }
+                false => stored,
+            };
+            sqlx::query!(
+                r#"INSERT INTO contents (content_hash, content, compressed, on_disk, ref_count)
+                   VALUES (?, ?, ?, ?, ?)"#,
+                content_hash,
+                stored,
+                row.compressed,
+                row.on_disk,
+                row.ref_count
+            )
+            .execute(&mut *transaction)
+            .await?;
+        }
+
+        sqlx::query!(
+            "UPDATE clips SET content_hash = ? WHERE content_hash = ?",
+            content_hash,
+            legacy_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE attachments SET content_hash = ? WHERE content_hash = ?",
+            content_hash,
+            legacy_hash
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!("DELETE FROM contents WHERE content_hash = ?", legacy_hash)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        blobs.publish()?;
+
+        if row.on_disk {
+            store.remove_blob(&legacy_hash)?;
+        }
+        rehashed += 1;
+    }
+
+    Ok(rehashed)
+}
+
+pub struct BlobReport {
+    /// Files in the blob directory no `contents` row points to.
+    pub orphaned_files: Vec<String>,
+    /// Hashes of `contents` rows whose blob file is missing.
+    pub dangling_references: Vec<String>,
+}
+
+pub async fn check_blobs(
+    remove_orphans: bool,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<BlobReport> {
+    use std::collections::HashSet;
+
+    let referenced: HashSet<String> =
+        sqlx::query_scalar!("SELECT content_hash FROM contents WHERE on_disk")
+            .fetch_all(pool)
             .await?
-            .rows_affected(),
+            .into_iter()
+            .collect();
+    let files: HashSet<String> = store.list_blobs()?.into_iter().collect();
+
+    let mut orphaned_files: Vec<String> = files.difference(&referenced).cloned().collect();
+    let mut dangling_references: Vec<String> = referenced.difference(&files).cloned().collect();
+    orphaned_files.sort();
+    dangling_references.sort();
+
+    if remove_orphans {
+        for file in orphaned_files.iter() {
+            store.remove_blob(file)?;
+        }
+    }
+
+    Ok(BlobReport {
+        orphaned_files,
+        dangling_references,
+    })
+}
+
+/// Creates a collection, returning its short code.
+pub async fn new_collection<M: Into<model::NewCollection>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<String> {
+    let model = model.into();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"INSERT INTO collections (collection_id, short_code, title, password, posted_at)
+           VALUES (?, ?, ?, ?, ?)"#,
+        model.collection_id,
+        model.short_code,
+        model.title,
+        model.password,
+        model.posted_at,
     )
+    .execute(&mut *transaction)
+    .await?;
+
+    insert_collection_clips(&model.collection_id, &model.clips, &mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(model.short_code)
+}
+
+/// Adds `clips` to a collection, in order.
+async fn insert_collection_clips(
+    collection_id: &str,
+    clips: &[String],
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    for (position, short_code) in clips.iter().enumerate() {
+        let position = position as i64;
+        let inserted = sqlx::query!(
+            r#"INSERT INTO collection_clips (collection_id, clip_id, position)
+               SELECT ?, clip_id, ? FROM clips WHERE short_code = ? AND deleted_at IS NULL"#,
+            collection_id,
+            position,
+            short_code,
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected();
+
+        if inserted == 0 {
+            return Err(DataError::MissingClip(short_code.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn get_collection(short_code: &str, pool: &DatabasePool) -> Result<model::Collection> {
+    Ok(sqlx::query_as!(
+        model::Collection,
+        "SELECT short_code, title, password, posted_at FROM collections WHERE short_code = ?",
+        short_code
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Short codes of the clips in a collection, in order.
+pub async fn get_collection_clips(short_code: &str, pool: &DatabasePool) -> Result<Vec<String>> {
+    Ok(sqlx::query_scalar!(
+        r#"SELECT clips.short_code
+           FROM collection_clips
+           INNER JOIN collections ON collections.collection_id = collection_clips.collection_id
+           INNER JOIN clips ON clips.clip_id = collection_clips.clip_id
+           WHERE collections.short_code = ?
+           ORDER BY collection_clips.position"#,
+        short_code
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Replaces the clips of a collection, keeping the given order.
+pub async fn set_collection_clips(
+    short_code: &str,
+    clips: &[String],
+    pool: &DatabasePool,
+) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+
+    let collection_id = sqlx::query_scalar!(
+        "SELECT collection_id FROM collections WHERE short_code = ?",
+        short_code
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM collection_clips WHERE collection_id = ?",
+        collection_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    insert_collection_clips(&collection_id, clips, &mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+pub async fn new_webhook<M: Into<model::NewWebhook>>(
+    model: M,
+    pool: &DatabasePool,
+) -> Result<model::Webhook> {
+    let model = model.into();
+
+    sqlx::query!(
+        r#"INSERT INTO webhooks (webhook_id, url, secret, events, created_at)
+           VALUES (?, ?, ?, ?, ?)"#,
+        model.webhook_id,
+        model.url,
+        model.secret,
+        model.events,
+        model.created_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(sqlx::query_as!(
+        model::Webhook,
+        "SELECT * FROM webhooks WHERE webhook_id = ?",
+        model.webhook_id
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+pub async fn list_webhooks(pool: &DatabasePool) -> Result<Vec<model::Webhook>> {
+    Ok(
+        sqlx::query_as!(model::Webhook, "SELECT * FROM webhooks ORDER BY created_at")
+            .fetch_all(pool)
+            .await?,
+    )
+}
+
+/// Deletes a webhook along with its pending deliveries.
+pub async fn delete_webhook(webhook_id: &str, pool: &DatabasePool) -> Result<()> {
+    let deleted = sqlx::query!("DELETE FROM webhooks WHERE webhook_id = ?", webhook_id)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+    match deleted {
+        0 => Err(sqlx::Error::RowNotFound.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Queues a delivery of `payload` to every webhook subscribed to `event`.
+pub async fn enqueue_deliveries(event: &str, payload: &str, pool: &DatabasePool) -> Result<u64> {
+    let now = chrono::Utc::now().timestamp();
+
+    Ok(sqlx::query!(
+        r#"INSERT INTO webhook_deliveries (delivery_id, webhook_id, event, payload, next_attempt_at)
+           SELECT lower(hex(randomblob(16))), webhook_id, ?, ?, ?
+           FROM webhooks
+           WHERE events = '*' OR instr(',' || events || ',', ',' || ? || ',') > 0"#,
+        event,
+        payload,
+        now,
+        event,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+pub async fn due_deliveries(limit: u32, pool: &DatabasePool) -> Result<Vec<model::Delivery>> {
+    let now = chrono::Utc::now().timestamp();
+
+    Ok(sqlx::query_as!(
+        model::Delivery,
+        r#"SELECT
+            webhook_deliveries.delivery_id,
+            webhooks.url,
+            webhooks.secret,
+            webhook_deliveries.event,
+            webhook_deliveries.payload,
+            webhook_deliveries.attempts
+           FROM webhook_deliveries
+           INNER JOIN webhooks ON webhooks.webhook_id = webhook_deliveries.webhook_id
+           WHERE webhook_deliveries.next_attempt_at <= ?
+           ORDER BY webhook_deliveries.next_attempt_at
+           LIMIT ?"#,
+        now,
+        limit,
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+pub async fn complete_delivery(delivery_id: &str, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        "DELETE FROM webhook_deliveries WHERE delivery_id = ?",
+        delivery_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn retry_delivery(
+    delivery_id: &str,
+    attempts: i64,
+    error: &str,
+    next_attempt_at: i64,
+    pool: &DatabasePool,
+) -> Result<()> {
+    sqlx::query!(
+        r#"UPDATE webhook_deliveries
+           SET attempts = ?, last_error = ?, next_attempt_at = ?
+           WHERE delivery_id = ?"#,
+        attempts,
+        error,
+        next_attempt_at,
+        delivery_id,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Moves a delivery that will not be retried anymore to the dead letter log.
+pub async fn dead_letter_delivery(
+    delivery_id: &str,
+    attempts: i64,
+    error: &str,
+    pool: &DatabasePool,
+) -> Result<()> {
+    let now = chrono::Utc::now().timestamp();
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        r#"INSERT INTO webhook_dead_letters
+            (delivery_id, webhook_id, url, event, payload, attempts, last_error, failed_at)
+           SELECT
+            webhook_deliveries.delivery_id,
+            webhook_deliveries.webhook_id,
+            webhooks.url,
+            webhook_deliveries.event,
+            webhook_deliveries.payload,
+            ?,
+            ?,
+            ?
+           FROM webhook_deliveries
+           INNER JOIN webhooks ON webhooks.webhook_id = webhook_deliveries.webhook_id
+           WHERE webhook_deliveries.delivery_id = ?"#,
+        attempts,
+        error,
+        now,
+        delivery_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM webhook_deliveries WHERE delivery_id = ?",
+        delivery_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+pub async fn list_dead_letters(pool: &DatabasePool) -> Result<Vec<model::DeadLetter>> {
+    Ok(sqlx::query_as!(
+        model::DeadLetter,
+        "SELECT * FROM webhook_dead_letters ORDER BY failed_at DESC"
+    )
+    .fetch_all(pool)
+    .await?)
 }
 
 #[cfg(test)]
 pub mod test {
+    use crate::data::content::ContentStore;
     use crate::data::test::*;
     use crate::data::*;
     use crate::test::async_runtime;
 
     fn model_new_clip(short_code: &str) -> model::NewClip {
         use chrono::Utc;
+        let content = format!("content for clip '{}'", short_code);
+
         model::NewClip {
             clip_id: DbId::new().into(),
-            content: format!("content for clip '{}'", short_code),
+            content_hash: content::hash(&content),
+            content,
             title: None,
             short_code: short_code.into(),
             posted_at: Utc::now().timestamp(),
             expires_at: None,
             password: None,
+            tags: vec![],
+            language: None,
+            detected_language: None,
+            forked_from: None,
         }
     }
 
@@ -174,13 +1299,401 @@ pub mod test {
         let rt = async_runtime();
         let db = new_db(rt.handle());
         let pool = db.get_pool();
+        let store = &ContentStore::default();
 
         let clip =
-            rt.block_on(async move { super::new_clip(model_new_clip("1"), &pool.clone()).await });
+            rt.block_on(
+                async move { super::new_clip(model_new_clip("1"), store, &pool.clone()).await },
+            );
         assert!(clip.is_ok());
 
         let clip = clip.unwrap();
         assert!(clip.short_code == "1");
-        assert!(clip.content == format!("content for clip '1'"));
+        assert!(clip.content == b"content for clip '1'");
+    }
+
+    #[test]
+    fn patch_changes_only_given_fields() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let mut new_clip = model_new_clip("1");
+        new_clip.title = Some("title".to_owned());
+        new_clip.expires_at = Some(1);
+
+        let clip = rt.block_on(async move {
+            super::new_clip(new_clip, store, pool).await?;
+            let patch = model::PatchClip {
+                short_code: "1".to_owned(),
+                content_hash: None,
+                content: None,
+                title: Some(None),
+                expires_at: None,
+                password: None,
+                language: None,
+                detected_language: None,
+            };
+            super::patch_clip(patch, None, store, pool).await
+        });
+
+        let clip = clip.expect("failed to patch clip");
+        assert_eq!(clip.title, None);
+        assert!(clip.expires_at.is_some());
+        assert_eq!(clip.content, b"content for clip '1'");
+    }
+
+    #[test]
+    fn stale_versions_are_rejected() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let patch = || model::PatchClip {
+            short_code: "1".to_owned(),
+            content_hash: None,
+            content: None,
+            title: Some(Some("title".to_owned())),
+            expires_at: None,
+            password: None,
+            language: None,
+            detected_language: None,
+        };
+
+        rt.block_on(async move {
+            let clip = super::new_clip(model_new_clip("1"), store, pool)
+                .await
+                .unwrap();
+            assert_eq!(clip.version, 1);
+
+            let clip = super::patch_clip(patch(), Some(&[1]), store, pool)
+                .await
+                .unwrap();
+            assert_eq!(clip.version, 2);
+
+            let stale = super::patch_clip(patch(), Some(&[1]), store, pool).await;
+            assert!(matches!(stale, Err(DataError::VersionMismatch)));
+            assert_eq!(
+                super::get_clip("1".to_owned(), store, pool)
+                    .await
+                    .unwrap()
+                    .version,
+                2
+            );
+        });
+    }
+
+    #[test]
+    fn identical_contents_are_stored_once() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let expired_clip = |short_code: &str| {
+            let mut clip = model_new_clip(short_code);
+            clip.content = "shared content".to_owned();
+            clip.content_hash = content::hash(&clip.content);
+            clip.expires_at = Some(clip.posted_at - 60);
+            clip
+        };
+
+        rt.block_on(async move {
+            super::new_clip(expired_clip("1"), store, pool)
+                .await
+                .unwrap();
+            super::new_clip(expired_clip("2"), store, pool)
+                .await
+                .unwrap();
+
+            let (count, ref_count): (i64, i64) =
+                sqlx::query_as("SELECT COUNT(*), SUM(ref_count) FROM contents")
+                    .fetch_one(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(ref_count, 2);
+
+            assert_eq!(super::trash_expired(pool).await.unwrap().len(), 2);
+            assert_eq!(
+                super::delete_unreferenced_contents(store, pool)
+                    .await
+                    .unwrap(),
+                0
+            );
+
+            let now = chrono::Utc::now().timestamp();
+            assert_eq!(super::purge_trash(now, pool).await.unwrap().len(), 2);
+            assert_eq!(
+                super::delete_unreferenced_contents(store, pool)
+                    .await
+                    .unwrap(),
+                1
+            );
+        });
+    }
+
+    #[test]
+    fn forks_share_the_attachment() {
+        use crate::service::ask;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let mut original = model_new_clip("1");
+        original.expires_at = Some(original.posted_at - 60);
+        let attachment =
+            ask::NewAttachment::new("report.bin", "application/octet-stream", vec![7; 16]).unwrap();
+        let mut fork = model_new_clip("2");
+        fork.forked_from = Some("1".to_owned());
+
+        rt.block_on(async move {
+            super::new_clip_with_attachment(original, attachment, store, pool)
+                .await
+                .unwrap();
+            super::new_clip(fork, store, pool).await.unwrap();
+
+            let attachment = super::get_attachment("2".to_owned(), store, pool)
+                .await
+                .unwrap();
+            assert_eq!(attachment.file_name, "report.bin");
+
+            super::trash_expired(pool).await.unwrap();
+            let now = chrono::Utc::now().timestamp();
+            super::purge_trash(now, pool).await.unwrap();
+            super::delete_unreferenced_contents(store, pool)
+                .await
+                .unwrap();
+
+            let attachment = super::get_attachment("2".to_owned(), store, pool)
+                .await
+                .unwrap();
+            assert_eq!(
+                content::decode_bytes(attachment.content, attachment.compressed).unwrap(),
+                vec![7; 16]
+            );
+        });
+    }
+
+    #[test]
+    fn detected_languages_are_kept_apart() {
+        use crate::service::ask;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let req = ask::test::new_clip("fn main() {\n    let mut x = 1;\n}\n");
+
+        let clip = rt.block_on(async move {
+            let clip = super::new_clip(model::NewClip::from(req), store, pool)
+                .await
+                .unwrap();
+            super::get_clip(clip.short_code, store, pool).await.unwrap()
+        });
+
+        let clip = crate::domain::Clip::try_from(clip).unwrap();
+        assert_eq!(clip.language.into_inner(), None);
+        assert_eq!(clip.detected_language.into_inner(), Some("Rust".to_owned()));
+    }
+
+    #[test]
+    fn legacy_contents_are_rehashed() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        rt.block_on(async move {
+            let mut copy = model_new_clip("2");
+            copy.content = model_new_clip("1").content;
+            copy.content_hash = content::hash(&copy.content);
+            let content_hash = copy.content_hash.clone();
+            super::new_clip(model_new_clip("1"), store, pool)
+                .await
+                .unwrap();
+            super::new_clip(copy, store, pool).await.unwrap();
+
+            // as left behind by the contents migration
+            let mut transaction = pool.begin().await.unwrap();
+            sqlx::query(
+                r#"INSERT INTO contents (content_hash, content, compressed, on_disk, ref_count)
+                   SELECT 'legacy-2', content, compressed, on_disk, 1 FROM contents"#,
+            )
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+            sqlx::query(
+                r#"UPDATE clips SET content_hash = 'legacy-2' WHERE short_code = '2';
+                   UPDATE contents SET ref_count = 1"#,
+            )
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+            transaction.commit().await.unwrap();
+
+            assert_eq!(super::rehash_legacy_contents(store, pool).await.unwrap(), 1);
+
+            let rows: Vec<(String, i64)> =
+                sqlx::query_as("SELECT content_hash, ref_count FROM contents")
+                    .fetch_all(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(rows, vec![(content_hash, 2)]);
+            let clip = super::get_clip("2".to_owned(), store, pool).await.unwrap();
+            assert_eq!(clip.content, model_new_clip("1").content.into_bytes());
+        });
+    }
+
+    #[test]
+    fn large_contents_are_compressed() {
+        use crate::domain::Clip;
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let store = &ContentStore::default();
+
+        let mut clip = model_new_clip("1");
+        clip.content = "log line\n".repeat(content::DEFAULT_COMPRESSION_THRESHOLD);
+        clip.content_hash = content::hash(&clip.content);
+        let expected = clip.content.clone();
+
+        rt.block_on(async move {
+            let clip = super::new_clip(clip, store, pool).await.unwrap();
+            assert!(clip.compressed);
+            assert!(clip.content.len() < expected.len());
+
+            let clip: Clip = clip.try_into().unwrap();
+            assert_eq!(clip.content.as_str(), expected);
+        });
+    }
+
+    #[test]
+    fn large_contents_are_stored_as_blobs() {
+        use rand::{distributions::Alphanumeric, Rng};
+
+        let dir = std::env::temp_dir().join(format!("clipstash-blobs-{}", DbId::new()));
+        let store = &ContentStore {
+            blobs: Some(content::BlobStore {
+                dir: dir.clone(),
+                threshold: 64 * 1024,
+            }),
+            ..Default::default()
+        };
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+
+        let mut clip = model_new_clip("1");
+        clip.content = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(128 * 1024)
+            .map(char::from)
+            .collect();
+        clip.content_hash = content::hash(&clip.content);
+        clip.expires_at = Some(clip.posted_at - 60);
+        let expected = clip.content.clone();
+        let blob = content::blob_path(&dir, &clip.content_hash);
+
+        rt.block_on(async move {
+            let clip = super::new_clip(clip, store, pool).await.unwrap();
+            assert!(clip.on_disk);
+            assert!(blob.exists());
+            assert_eq!(
+                content::decode(clip.content, clip.compressed).unwrap(),
+                expected
+            );
+
+            std::fs::write(dir.join("orphan"), b"orphan").unwrap();
+            std::fs::write(dir.join(".in-flight.tmp"), b"partial").unwrap();
+            let report = super::check_blobs(true, store, pool).await.unwrap();
+            assert_eq!(report.orphaned_files, vec!["orphan".to_owned()]);
+            assert!(report.dangling_references.is_empty());
+            assert!(!dir.join("orphan").exists());
+            assert!(dir.join(".in-flight.tmp").exists());
+
+            super::trash_expired(pool).await.unwrap();
+            let now = chrono::Utc::now().timestamp();
+            super::purge_trash(now, pool).await.unwrap();
+            super::delete_unreferenced_contents(store, pool)
+                .await
+                .unwrap();
+            assert!(!blob.exists());
+
+            std::fs::remove_dir_all(dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn cleanup_keeps_contents_taken_back_concurrently() {
+        use rand::{distributions::Alphanumeric, Rng};
+
+        let dir = std::env::temp_dir().join(format!("clipstash-blobs-{}", DbId::new()));
+        let store = ContentStore {
+            blobs: Some(content::BlobStore {
+                dir: dir.clone(),
+                threshold: 1024,
+            }),
+            ..Default::default()
+        };
+
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+
+        let shared: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(4 * 1024)
+            .map(char::from)
+            .collect();
+        let expired_clip = |short_code: String| {
+            let mut clip = model_new_clip(&short_code);
+            clip.content = shared.clone();
+            clip.content_hash = content::hash(&clip.content);
+            clip.expires_at = Some(clip.posted_at - 60);
+            clip
+        };
+
+        let shared = &shared;
+        rt.block_on(async move {
+            for i in 0..20 {
+                super::new_clip(expired_clip(format!("old{}", i)), &store, &pool)
+                    .await
+                    .unwrap();
+                super::trash_expired(&pool).await.unwrap();
+                let now = chrono::Utc::now().timestamp();
+                super::purge_trash(now, &pool).await.unwrap();
+
+                let create = tokio::spawn({
+                    let (store, pool) = (store.clone(), pool.clone());
+                    let clip = expired_clip(format!("new{}", i));
+                    async move { super::new_clip(clip, &store, &pool).await }
+                });
+                let cleanup = tokio::spawn({
+                    let (store, pool) = (store.clone(), pool.clone());
+                    async move { super::delete_unreferenced_contents(&store, &pool).await }
+                });
+                let (created, cleaned) = tokio::join!(create, cleanup);
+                created.unwrap().unwrap();
+                cleaned.unwrap().unwrap();
+
+                let clip = super::get_clip(format!("new{}", i), &store, &pool)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    content::decode(clip.content, clip.compressed).unwrap(),
+                    *shared
+                );
+            }
+
+            std::fs::remove_dir_all(dir).unwrap();
+        });
     }
 }
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+/// Metadata of a file uploaded alongside a clip. The file itself is served
+/// from its own endpoint and never embedded in the clip.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attachment {
+    file_name: String,
+    content_type: String,
+    size: u64,
+}
+
+impl Attachment {
+    pub fn new(file_name: String, content_type: String, size: u64) -> Self {
+        Self {
+            file_name,
+            content_type,
+            size,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.file_name.as_str()
+    }
+
+    pub fn content_type(&self) -> &str {
+        self.content_type.as_str()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
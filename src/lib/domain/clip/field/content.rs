@@ -4,6 +4,7 @@ use serde::{Deserialize, Serialize};
 use crate::domain::clip::ClipError;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String")]
 pub struct Content(String);
 
 impl Content {
@@ -14,6 +15,11 @@ impl Content {
         }
     }
 
+    /// Wraps content that was validated when it was stored.
+    pub(crate) fn stored(content: String) -> Self {
+        Content(content)
+    }
+
     pub fn into_inner(self) -> String {
         self.0
     }
@@ -23,6 +29,14 @@ impl Content {
     }
 }
 
+impl TryFrom<String> for Content {
+    type Error = ClipError;
+
+    fn try_from(content: String) -> Result<Self, Self::Error> {
+        Self::new(&content)
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Content {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
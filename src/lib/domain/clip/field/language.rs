@@ -0,0 +1,86 @@
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::domain::clip::{syntax, ClipError};
+
+/// Syntax name of Markdown; clips in it are rendered on the clip page.
+pub const MARKDOWN: &str = "Markdown";
+
+/// Language a clip is highlighted as, stored by its canonical syntax name so
+/// `rs`, `rust` and `Rust` all end up the same.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "Option<String>")]
+pub struct Language(Option<String>);
+
+impl Language {
+    pub fn new<T: Into<Option<String>>>(language: T) -> Result<Self, ClipError> {
+        let language: Option<String> = language.into();
+
+        match language.as_deref().map(str::trim) {
+            None | Some("") => Ok(Self(None)),
+            Some(language) => match syntax::find(language) {
+                Some(syntax) => Ok(Self(Some(syntax.name.clone()))),
+                None => Err(ClipError::UnknownLanguage(language.to_owned())),
+            },
+        }
+    }
+
+    /// Wraps a language that was validated when it was stored.
+    pub(crate) fn stored(language: Option<String>) -> Self {
+        Self(language)
+    }
+
+    /// Guesses the language of `content`.
+    pub fn detect(content: &str) -> Self {
+        Self(syntax::detect(content).map(str::to_owned))
+    }
+
+    /// Keeps an explicitly chosen language, or falls back to `guess`.
+    pub fn or<'a>(&'a self, guess: &'a Self) -> &'a Self {
+        match self.0 {
+            Some(_) => self,
+            None => guess,
+        }
+    }
+
+    pub fn is_markdown(&self) -> bool {
+        self.0.as_deref() == Some(MARKDOWN)
+    }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+}
+
+impl TryFrom<Option<String>> for Language {
+    type Error = ClipError;
+
+    fn try_from(language: Option<String>) -> Result<Self, Self::Error> {
+        Self::new(language)
+    }
+}
+
+impl FromStr for Language {
+    type Err = ClipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.to_string())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Language {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::new(field.value.to_owned())
+            .map_err(|err| form::Error::validation(format!("{}", err)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(None))
+    }
+}
@@ -21,3 +21,15 @@ pub use password::Password;
 
 mod hits;
 pub use hits::Hits;
+
+mod version;
+pub use version::Version;
+
+mod attachment;
+pub use attachment::Attachment;
+
+mod tags;
+pub use tags::{Tags, MAX_TAGS, MAX_TAG_LENGTH};
+
+mod language;
+pub use language::Language;
@@ -4,7 +4,7 @@ use std::str::FromStr;
 
 use crate::domain::clip::ClipError;
 
-#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, PartialOrd)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub struct Password(Option<String>);
 
 impl Password {
@@ -32,12 +32,6 @@ impl Password {
     }
 }
 
-impl Default for Password {
-    fn default() -> Self {
-        Self(None)
-    }
-}
-
 impl FromStr for Password {
     type Err = ClipError;
 
@@ -0,0 +1,117 @@
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::domain::clip::ClipError;
+
+pub const MAX_TAGS: usize = 10;
+pub const MAX_TAG_LENGTH: usize = 32;
+
+/// Free-form labels used to group clips. Tags are trimmed and lowercased so
+/// `Incident-42` and `incident-42 ` end up in the same group.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    pub fn new<I, T>(tags: I) -> Result<Self, ClipError>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let mut normalized = Vec::new();
+        for tag in tags {
+            let tag = Self::normalize(tag.as_ref())?;
+            if !tag.is_empty() && !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+
+        if normalized.len() > MAX_TAGS {
+            return Err(ClipError::TooManyTags(MAX_TAGS));
+        }
+        normalized.sort();
+
+        Ok(Self(normalized))
+    }
+
+    /// Normalizes a single tag, as used when filtering by one.
+    pub fn normalize(tag: &str) -> Result<String, ClipError> {
+        let tag = tag.trim().to_lowercase();
+
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(ClipError::InvalidTag(format!(
+                "tags may be at most {} characters long",
+                MAX_TAG_LENGTH
+            )));
+        }
+        if tag.contains(|c: char| c == ',' || c.is_control()) {
+            return Err(ClipError::InvalidTag(
+                "tags may not contain commas or control characters".to_owned(),
+            ));
+        }
+
+        Ok(tag)
+    }
+
+    /// Wraps tags that were validated when they were stored.
+    pub(crate) fn stored(tags: Vec<String>) -> Self {
+        Self(tags)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    pub fn into_inner(self) -> Vec<String> {
+        self.0
+    }
+}
+
+impl TryFrom<Vec<String>> for Tags {
+    type Error = ClipError;
+
+    fn try_from(tags: Vec<String>) -> Result<Self, Self::Error> {
+        Self::new(tags)
+    }
+}
+
+/// Parses a comma-separated list, as typed into the new clip form.
+impl FromStr for Tags {
+    type Err = ClipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.split(','))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Tags {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value)
+            .map_err(|err| form::Error::validation(format!("{}", err)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(Vec::new()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn tags_are_normalized() {
+        let tags: Tags = " Incident-42, backend,,incident-42 ".parse().unwrap();
+        assert_eq!(tags.into_inner(), vec!["backend", "incident-42"]);
+
+        assert!(Tags::new(["a,b"]).is_err());
+        assert!(Tags::new(["x".repeat(MAX_TAG_LENGTH + 1)]).is_err());
+        assert!(Tags::new((0..=MAX_TAGS).map(|n| n.to_string())).is_err());
+    }
+}
@@ -4,7 +4,7 @@ use std::str::FromStr;
 
 use crate::domain::clip::ClipError;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Title(Option<String>);
 
 impl Title {
@@ -23,14 +23,17 @@ impl Title {
         }
     }
 
-    pub fn into_inner(self) -> Option<String> {
-        self.0
+    /// Wraps a title that was validated when it was stored.
+    pub(crate) fn stored(title: Option<String>) -> Self {
+        Self(title)
     }
-}
 
-impl Default for Title {
-    fn default() -> Self {
-        Self::new(None)
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
     }
 }
 
@@ -0,0 +1,13 @@
+use derive_more::{Constructor, Display};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, Constructor, Display, PartialEq, Eq,
+)]
+pub struct Version(u64);
+
+impl Version {
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+}
@@ -0,0 +1,52 @@
+use super::{field, ClipError};
+
+pub const DEFAULT_MAX_CONTENT_SIZE: usize = 512 * 1024;
+pub const DEFAULT_MAX_TITLE_LENGTH: usize = 100;
+pub const DEFAULT_MAX_ATTACHMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Sizes new clips may not exceed. They are checked where requests come in,
+/// never when stored clips are read, so lowering them keeps old clips readable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClipLimits {
+    /// Largest accepted clip content, in bytes.
+    pub max_content_size: usize,
+    /// Longest accepted clip title, in characters.
+    pub max_title_length: usize,
+    /// Largest accepted file attachment, in bytes.
+    pub max_attachment_size: usize,
+}
+
+impl Default for ClipLimits {
+    fn default() -> Self {
+        Self {
+            max_content_size: DEFAULT_MAX_CONTENT_SIZE,
+            max_title_length: DEFAULT_MAX_TITLE_LENGTH,
+            max_attachment_size: DEFAULT_MAX_ATTACHMENT_SIZE,
+        }
+    }
+}
+
+impl ClipLimits {
+    pub fn check_content(&self, content: &field::Content) -> Result<(), ClipError> {
+        match content.as_str().len() > self.max_content_size {
+            true => Err(ClipError::ContentTooLarge(self.max_content_size)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn check_title(&self, title: &field::Title) -> Result<(), ClipError> {
+        match title.as_deref() {
+            Some(title) if title.chars().count() > self.max_title_length => {
+                Err(ClipError::TitleTooLong(self.max_title_length))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_attachment(&self, size: usize) -> Result<(), ClipError> {
+        match size > self.max_attachment_size {
+            true => Err(ClipError::AttachmentTooLarge(self.max_attachment_size)),
+            false => Ok(()),
+        }
+    }
+}
@@ -1,16 +1,37 @@
 pub mod field;
+pub mod limits;
+pub mod stats;
+pub mod syntax;
 
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::Time;
+
 #[derive(Debug, Error)]
 pub enum ClipError {
     #[error("invalid password: {0}")]
     InvalidPassword(String),
     #[error("invalid title: {0}")]
     InvalidTitle(String),
+    #[error("title exceeds the maximum length of {0} characters")]
+    TitleTooLong(usize),
+    #[error("invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("a clip may have at most {0} tags")]
+    TooManyTags(usize),
+    #[error("unknown language: {0}")]
+    UnknownLanguage(String),
     #[error("empty content")]
     EmptyContent,
+    #[error("content exceeds the maximum size of {0} bytes")]
+    ContentTooLarge(usize),
+    #[error("empty attachment")]
+    EmptyAttachment,
+    #[error("attachment exceeds the maximum size of {0} bytes")]
+    AttachmentTooLarge(usize),
+    #[error("content decode error: {0}")]
+    ContentDecode(#[from] std::io::Error),
     #[error("invalid date: {0}")]
     InvalidDate(String),
     #[error("date parse error: {0}")]
@@ -32,4 +53,46 @@ pub struct Clip {
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
     pub hits: field::Hits,
+    #[serde(default)]
+    pub version: field::Version,
+    #[serde(default)]
+    pub tags: field::Tags,
+    #[serde(default)]
+    pub language: field::Language,
+    /// Guessed from the content when it was saved. Only used when no
+    /// `language` was chosen.
+    #[serde(default)]
+    pub detected_language: field::Language,
+    /// The clip this one was copied from, if any.
+    #[serde(default)]
+    pub forked_from: Option<field::ShortCode>,
+    /// Unprotected copies of this clip.
+    #[serde(default)]
+    pub forks: Vec<field::ShortCode>,
+    #[serde(default)]
+    pub attachment: Option<field::Attachment>,
+}
+
+/// A clip in the trash, waiting to be restored or purged. Protected clips
+/// show only their short code and when they were trashed, like collection
+/// members.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TrashedClip {
+    pub short_code: field::ShortCode,
+    pub title: field::Title,
+    pub expires_at: field::ExpiresAt,
+    pub deleted_at: Time,
+    pub protected: bool,
+}
+
+/// What listings show of a clip; the content stays behind its short code.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipSummary {
+    pub short_code: field::ShortCode,
+    pub title: field::Title,
+    pub posted_at: field::PostedAt,
+    pub expires_at: field::ExpiresAt,
+    pub protected: bool,
+    pub hits: field::Hits,
+    pub tags: field::Tags,
 }
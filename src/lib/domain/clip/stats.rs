@@ -0,0 +1,96 @@
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use super::field;
+
+pub const DEFAULT_DAYS: u32 = 30;
+pub const MAX_DAYS: u32 = 365;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DailyViews {
+    pub day: NaiveDate,
+    pub views: u64,
+    /// Views by different visitors.
+    pub unique_views: u64,
+}
+
+/// When a clip was read: its total hits, and its views per day, oldest first.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ClipStats {
+    pub short_code: field::ShortCode,
+    pub hits: field::Hits,
+    pub days: Vec<DailyViews>,
+}
+
+impl ClipStats {
+    /// Covers the `days` days up to `today`, filling in days without views.
+    pub fn new(
+        short_code: field::ShortCode,
+        hits: field::Hits,
+        recorded: Vec<DailyViews>,
+        days: u32,
+        today: NaiveDate,
+    ) -> Self {
+        let days = Self::first_day(days, today)
+            .iter_days()
+            .take_while(|day| *day <= today)
+            .map(|day| {
+                recorded
+                    .iter()
+                    .find(|recorded| recorded.day == day)
+                    .copied()
+                    .unwrap_or(DailyViews {
+                        day,
+                        views: 0,
+                        unique_views: 0,
+                    })
+            })
+            .collect();
+
+        Self {
+            short_code,
+            hits,
+            days,
+        }
+    }
+
+    /// The oldest day covered by stats over `days` days, at most `MAX_DAYS`.
+    pub fn first_day(days: u32, today: NaiveDate) -> NaiveDate {
+        today - Duration::days(i64::from(days.clamp(1, MAX_DAYS)) - 1)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn fills_in_days_without_views() {
+        let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        let recorded = vec![
+            DailyViews {
+                day: day(16),
+                views: 4,
+                unique_views: 2,
+            },
+            DailyViews {
+                day: day(18),
+                views: 1,
+                unique_views: 1,
+            },
+        ];
+
+        let stats = ClipStats::new("abc".into(), field::Hits::new(5), recorded, 4, day(18));
+        let views: Vec<_> = stats.days.iter().map(|d| (d.day, d.views)).collect();
+        assert_eq!(
+            views,
+            [(day(15), 0), (day(16), 4), (day(17), 0), (day(18), 1)]
+        );
+
+        assert_eq!(ClipStats::first_day(0, day(18)), day(18));
+        assert_eq!(
+            ClipStats::first_day(10_000, day(18)).to_string(),
+            "2025-10-19"
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::OnceLock;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// Keywords that give a language away. A language is only guessed when at
+/// least two of its hints show up in the content.
+const HINTS: &[(&str, &[&str])] = &[
+    (
+        "Rust",
+        &[
+            "fn ",
+            "let mut ",
+            "impl ",
+            "pub fn ",
+            "use std::",
+            "-> ",
+            "::",
+        ],
+    ),
+    (
+        "Python",
+        &["def ", "import ", "self.", "elif ", "print(", "None"],
+    ),
+    ("Go", &["package ", "func ", ":= ", "fmt.", "err != nil"]),
+    (
+        "JavaScript",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "require(",
+            "===",
+        ],
+    ),
+    (
+        "Java",
+        &[
+            "public class ",
+            "System.out",
+            "import java.",
+            "private ",
+            "@Override",
+        ],
+    ),
+    ("C", &["#include", "int main(", "printf(", "malloc(", "->"]),
+    (
+        "SQL",
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO ",
+            "CREATE TABLE ",
+            "JOIN ",
+        ],
+    ),
+    (
+        "HTML",
+        &["<!DOCTYPE html", "<html", "<head>", "<body", "<div", "</"],
+    ),
+    (
+        "Bourne Again Shell (bash)",
+        &["echo ", "$(", "then\n", "fi\n", "export ", "done\n"],
+    ),
+];
+
+pub fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Looks a language up by name or file extension, ignoring case.
+pub fn find(language: &str) -> Option<&'static SyntaxReference> {
+    let set = syntax_set();
+    set.find_syntax_by_name(language)
+        .or_else(|| set.find_syntax_by_token(language))
+}
+
+/// Guesses the language of `content`, by its first line (shebangs, XML
+/// declarations) and then by keywords.
+pub fn detect(content: &str) -> Option<&'static str> {
+    let first_line = content.lines().next().unwrap_or_default();
+    if let Some(syntax) = syntax_set().find_syntax_by_first_line(first_line) {
+        return Some(syntax.name.as_str());
+    }
+
+    let trimmed = content.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some("JSON");
+    }
+
+    HINTS
+        .iter()
+        .map(|(language, hints)| {
+            let score = hints.iter().filter(|hint| content.contains(*hint)).count();
+            (*language, score)
+        })
+        .filter(|(_, score)| *score >= 2)
+        .max_by_key(|(_, score)| *score)
+        .map(|(language, _)| language)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn detects_languages() {
+        assert_eq!(detect("#!/usr/bin/env python3\nprint(1)"), Some("Python"));
+        assert_eq!(detect(r#"{"key": [1, 2]}"#), Some("JSON"));
+        assert_eq!(detect("fn main() {\n    let mut x = 1;\n}\n"), Some("Rust"));
+        assert_eq!(detect("SELECT name FROM users WHERE id = 1;"), Some("SQL"));
+        assert_eq!(detect("just some notes"), None);
+
+        for (language, _) in HINTS {
+            assert!(
+                find(language).is_some(),
+                "{} is not a known syntax",
+                language
+            );
+        }
+        assert_eq!(find("rs").unwrap().name, "Rust");
+        assert_eq!(find("python").unwrap().name, "Python");
+    }
+}
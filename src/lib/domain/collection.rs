@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+use crate::domain::clip::field;
+use crate::{Clip, ShortCode};
+
+/// Most clips a single collection may hold.
+pub const MAX_CLIPS: usize = 100;
+
+/// Longest preview shown for a member clip, in characters and in lines.
+const PREVIEW_LENGTH: usize = 300;
+const PREVIEW_LINES: usize = 6;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CollectionError {
+    #[error("a collection needs at least one clip")]
+    Empty,
+    #[error("a collection may hold at most {0} clips")]
+    TooManyClips(usize),
+    #[error("clip {0} is listed more than once")]
+    DuplicateClip(String),
+    #[error("clip {0} does not exist")]
+    UnknownClip(String),
+}
+
+/// Related clips shared under a single short code, in the order they were given.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Collection {
+    pub short_code: ShortCode,
+    pub title: field::Title,
+    pub posted_at: field::PostedAt,
+    pub password: field::Password,
+    pub clips: Vec<Member>,
+}
+
+/// A clip as listed in a collection. Protected clips keep their title and
+/// content to themselves.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Member {
+    pub short_code: ShortCode,
+    pub title: field::Title,
+    pub protected: bool,
+    pub preview: Option<String>,
+    pub truncated: bool,
+    pub hits: field::Hits,
+    pub tags: field::Tags,
+}
+
+impl From<Clip> for Member {
+    fn from(clip: Clip) -> Self {
+        let protected = clip.password.has_password();
+        let (title, preview, truncated) = if protected {
+            (field::Title::default(), None, false)
+        } else {
+            let (preview, truncated) = preview(clip.content.as_str());
+            (clip.title, Some(preview), truncated)
+        };
+
+        Self {
+            short_code: clip.short_code,
+            title,
+            protected,
+            preview,
+            truncated,
+            hits: clip.hits,
+            tags: clip.tags,
+        }
+    }
+}
+
+/// The first few lines of `content`, and whether anything was cut off.
+fn preview(content: &str) -> (String, bool) {
+    let mut lines = content.trim_end().lines();
+    let mut preview = lines
+        .by_ref()
+        .take(PREVIEW_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut truncated = lines.next().is_some();
+
+    if let Some((end, _)) = preview.char_indices().nth(PREVIEW_LENGTH) {
+        preview.truncate(end);
+        truncated = true;
+    }
+
+    (preview, truncated)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn previews_are_cut_short() {
+        assert_eq!(preview("one\ntwo\n"), ("one\ntwo".to_owned(), false));
+
+        let lines = (0..10)
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(preview(&lines), ("0\n1\n2\n3\n4\n5".to_owned(), true));
+
+        let (long, truncated) = preview(&"é".repeat(PREVIEW_LENGTH + 1));
+        assert_eq!(long.chars().count(), PREVIEW_LENGTH);
+        assert!(truncated);
+    }
+}
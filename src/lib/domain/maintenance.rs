@@ -1,22 +1,125 @@
-use crate::data::DatabasePool;
-use crate::service;
-use std::time::Duration;
+use crate::data::{content::ContentStore, DatabasePool};
+use crate::service::{self, events::ClipEvents};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
 
-pub struct Maintenance;
+#[derive(Clone, Debug)]
+pub struct MaintenanceConfig {
+    pub interval: Duration,
+    /// How long expired clips stay in the trash before they are purged.
+    pub trash_grace_period: Duration,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            trash_grace_period: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    runs: AtomicU64,
+    run_micros: AtomicU64,
+    last_run_micros: AtomicU64,
+    trashed: AtomicU64,
+    purged: AtomicU64,
+    visitors_forgotten: AtomicU64,
+    contents_deleted: AtomicU64,
+}
+
+/// What the maintenance task has done since the server started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    pub runs: u64,
+    /// Time spent in all runs together.
+    pub run_time: Duration,
+    pub last_run_time: Duration,
+    pub trashed: u64,
+    pub purged: u64,
+    pub visitors_forgotten: u64,
+    pub contents_deleted: u64,
+}
+
+pub struct Maintenance {
+    metrics: Arc<Metrics>,
+}
 
 impl Maintenance {
-    pub fn spawn(pool: DatabasePool, handle: Handle) -> Self {
-        handle.spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(10));
-
-            loop {
-                interval.tick().await;
-                if let Err(err) = service::action::delete_expired(&pool).await {
-                    eprintln!("failed to delete expired clips: {}", err);
+    pub fn spawn(
+        pool: DatabasePool,
+        store: ContentStore,
+        events: ClipEvents,
+        handle: Handle,
+        config: MaintenanceConfig,
+    ) -> Self {
+        let metrics = Arc::new(Metrics::default());
+        handle.spawn(Self::run(pool, store, events, config, Arc::clone(&metrics)));
+        Self { metrics }
+    }
+
+    async fn run(
+        pool: DatabasePool,
+        store: ContentStore,
+        events: ClipEvents,
+        config: MaintenanceConfig,
+        metrics: Arc<Metrics>,
+    ) {
+        let mut interval = tokio::time::interval(config.interval);
+
+        loop {
+            interval.tick().await;
+            let started = Instant::now();
+            match service::action::trash_expired(&events, &pool).await {
+                Ok(trashed) => {
+                    metrics.trashed.fetch_add(trashed, Ordering::Relaxed);
+                }
+                Err(err) => eprintln!("failed to trash expired clips: {}", err),
+            }
+            match service::action::purge_trash(config.trash_grace_period, &pool).await {
+                Ok(purged) => {
+                    metrics.purged.fetch_add(purged, Ordering::Relaxed);
+                }
+                Err(err) => eprintln!("failed to purge trashed clips: {}", err),
+            }
+            match service::action::forget_visitors(&pool).await {
+                Ok(forgotten) => {
+                    metrics
+                        .visitors_forgotten
+                        .fetch_add(forgotten, Ordering::Relaxed);
                 }
+                Err(err) => eprintln!("failed to forget visitors: {}", err),
             }
-        });
-        Self
+            match service::action::delete_unreferenced_contents(&store, &pool).await {
+                Ok(deleted) => {
+                    metrics
+                        .contents_deleted
+                        .fetch_add(deleted, Ordering::Relaxed);
+                }
+                Err(err) => eprintln!("failed to delete unreferenced contents: {}", err),
+            }
+
+            let micros = u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX);
+            metrics.runs.fetch_add(1, Ordering::Relaxed);
+            metrics.run_micros.fetch_add(micros, Ordering::Relaxed);
+            metrics.last_run_micros.store(micros, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> MaintenanceStats {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MaintenanceStats {
+            runs: load(&self.metrics.runs),
+            run_time: Duration::from_micros(load(&self.metrics.run_micros)),
+            last_run_time: Duration::from_micros(load(&self.metrics.last_run_micros)),
+            trashed: load(&self.metrics.trashed),
+            purged: load(&self.metrics.purged),
+            visitors_forgotten: load(&self.metrics.visitors_forgotten),
+            contents_deleted: load(&self.metrics.contents_deleted),
+        }
     }
 }
@@ -1,5 +1,7 @@
 pub mod clip;
+pub mod collection;
 pub mod maintenance;
 pub mod time;
+pub mod webhook;
 
 pub use clip::Clip;
@@ -0,0 +1,402 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+use crate::data::DatabasePool;
+use crate::service;
+use crate::{Clip, ShortCode, Time};
+
+pub const EVENT_HEADER: &str = "X-Clipstash-Event";
+pub const DELIVERY_HEADER: &str = "X-Clipstash-Delivery";
+pub const SIGNATURE_HEADER: &str = "X-Clipstash-Signature";
+
+/// Deliveries picked up by a single run of the worker.
+const DELIVERY_BATCH_SIZE: u32 = 20;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("invalid webhook url: {0}")]
+    InvalidUrl(String),
+    #[error("unknown webhook event: {0}")]
+    UnknownEvent(String),
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, strum::Display, strum::EnumString,
+)]
+pub enum Event {
+    #[serde(rename = "clip.created")]
+    #[strum(serialize = "clip.created")]
+    ClipCreated,
+    #[serde(rename = "clip.updated")]
+    #[strum(serialize = "clip.updated")]
+    ClipUpdated,
+    #[serde(rename = "clip.first_viewed")]
+    #[strum(serialize = "clip.first_viewed")]
+    ClipFirstViewed,
+    #[serde(rename = "clip.expired")]
+    #[strum(serialize = "clip.expired")]
+    ClipExpired,
+}
+
+/// A subscription to clip events. An empty event list subscribes to every event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub webhook_id: String,
+    pub url: String,
+    pub events: Vec<Event>,
+    pub created_at: Time,
+    /// Only returned when the webhook is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+/// Body posted to webhooks. Titles of password protected clips are left out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Payload {
+    pub event: Event,
+    pub occurred_at: Time,
+    pub short_code: ShortCode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Time>,
+}
+
+impl Payload {
+    pub fn new(event: Event, short_code: ShortCode) -> Self {
+        Self {
+            event,
+            occurred_at: Utc::now().into(),
+            short_code,
+            title: None,
+            expires_at: None,
+        }
+    }
+
+    pub fn for_clip(event: Event, clip: &Clip) -> Self {
+        let title = match clip.password.has_password() {
+            true => None,
+            false => clip.title.clone().into_inner(),
+        };
+
+        Self {
+            title,
+            expires_at: clip.expires_at.clone().into_inner(),
+            ..Self::new(event, clip.short_code.clone())
+        }
+    }
+}
+
+/// A queued delivery of one event to one webhook.
+#[derive(Clone, Debug)]
+pub struct Delivery {
+    pub delivery_id: String,
+    pub url: String,
+    pub secret: String,
+    pub event: Event,
+    pub payload: String,
+    pub attempts: u32,
+}
+
+/// A delivery that was given up on after too many failed attempts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeadLetter {
+    pub delivery_id: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub event: Event,
+    pub payload: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub failed_at: Time,
+}
+
+/// Signature sent in [`SIGNATURE_HEADER`]: the hex HMAC-SHA256 of the body, keyed with the webhook secret.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Clone, Debug)]
+pub struct WebhookConfig {
+    pub poll_interval: Duration,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_attempts: u32,
+    pub timeout: Duration,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            base_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60 * 60),
+            max_attempts: 8,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Delay before retrying a delivery that has failed `attempts` times.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Background worker delivering queued webhook events.
+pub struct Webhooks;
+
+impl Webhooks {
+    pub fn spawn(pool: DatabasePool, handle: Handle, config: WebhookConfig) -> Self {
+        handle.spawn(async move {
+            let client = match reqwest::Client::builder().timeout(config.timeout).build() {
+                Ok(client) => client,
+                Err(err) => {
+                    eprintln!("failed to build webhook client: {}", err);
+                    return;
+                }
+            };
+            let mut interval = tokio::time::interval(config.poll_interval);
+
+            loop {
+                interval.tick().await;
+                if let Err(err) = Self::deliver_due(&client, &pool, &config).await {
+                    eprintln!("failed to deliver webhooks: {}", err);
+                }
+            }
+        });
+        Self
+    }
+
+    async fn deliver_due(
+        client: &reqwest::Client,
+        pool: &DatabasePool,
+        config: &WebhookConfig,
+    ) -> Result<(), service::ServiceError> {
+        use service::action;
+
+        let deliveries = action::due_deliveries(DELIVERY_BATCH_SIZE, pool).await?;
+        for delivery in deliveries {
+            let err = match Self::send(client, &delivery).await {
+                Ok(()) => {
+                    action::complete_delivery(&delivery.delivery_id, pool).await?;
+                    continue;
+                }
+                Err(err) => err,
+            };
+
+            let attempts = delivery.attempts + 1;
+            if attempts >= config.max_attempts {
+                eprintln!(
+                    "giving up on webhook delivery {} of {} to {}: {}",
+                    delivery.delivery_id, delivery.event, delivery.url, err
+                );
+                action::dead_letter_delivery(&delivery.delivery_id, attempts, &err, pool).await?;
+            } else {
+                let retry_at = Utc::now() + config.backoff(attempts);
+                action::retry_delivery(&delivery.delivery_id, attempts, &err, retry_at, pool)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn send(client: &reqwest::Client, delivery: &Delivery) -> Result<(), String> {
+        let response = client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, delivery.event.to_string())
+            .header(DELIVERY_HEADER, &delivery.delivery_id)
+            .header(
+                SIGNATURE_HEADER,
+                sign(&delivery.secret, delivery.payload.as_bytes()),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(format!("webhook responded with {}", status)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use parking_lot::Mutex;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::data::test::new_db;
+    use crate::domain::clip::field;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+
+    struct StubRequest {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// A local HTTP endpoint answering every request with `status`.
+    fn stub_server(status: u16) -> (String, Arc<Mutex<Vec<StubRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind stub server");
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let received = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.expect("failed to accept connection");
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+                    }
+                }
+
+                let length = headers
+                    .get("content-length")
+                    .map(|length| length.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                received.lock().push(StubRequest {
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                });
+
+                let response = format!(
+                    "HTTP/1.1 {} Stub\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn wait_for<F: FnMut() -> bool>(mut done: F) {
+        let started = Instant::now();
+        while !done() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "timed out waiting for webhook delivery"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn config(max_attempts: u32) -> WebhookConfig {
+        WebhookConfig {
+            poll_interval: Duration::from_millis(20),
+            base_backoff: Duration::ZERO,
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    fn new_clip() -> ask::NewClip {
+        ask::NewClip {
+            title: field::Title::new(Some("hook".to_owned())),
+            ..ask::test::new_clip("hooked")
+        }
+    }
+
+    #[test]
+    fn delivers_signed_events() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        let (url, requests) = stub_server(200);
+
+        rt.block_on(async {
+            let req = ask::NewWebhook {
+                url,
+                secret: Some("s3cret".to_owned()),
+                events: vec![Event::ClipCreated],
+            };
+            action::new_webhook(req, &pool).await.unwrap();
+            action::new_clip(new_clip(), &Default::default(), &pool)
+                .await
+                .unwrap();
+
+            let unsubscribed = crate::data::query::enqueue_deliveries("clip.updated", "{}", &pool);
+            assert_eq!(unsubscribed.await.unwrap(), 0);
+        });
+
+        let _webhooks = Webhooks::spawn(pool.clone(), rt.handle().clone(), config(3));
+        wait_for(|| !requests.lock().is_empty());
+
+        let requests = requests.lock();
+        let request = &requests[0];
+        assert_eq!(request.headers["x-clipstash-event"], "clip.created");
+        assert_eq!(
+            request.headers["x-clipstash-signature"],
+            sign("s3cret", request.body.as_bytes())
+        );
+
+        let payload: Payload = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(payload.event, Event::ClipCreated);
+        assert_eq!(payload.title.as_deref(), Some("hook"));
+    }
+
+    #[test]
+    fn dead_letters_failing_deliveries() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        let (url, requests) = stub_server(500);
+
+        rt.block_on(async {
+            let req = ask::NewWebhook {
+                url,
+                secret: None,
+                events: vec![],
+            };
+            action::new_webhook(req, &pool).await.unwrap();
+            action::new_clip(new_clip(), &Default::default(), &pool)
+                .await
+                .unwrap();
+        });
+
+        let _webhooks = Webhooks::spawn(pool.clone(), rt.handle().clone(), config(3));
+        let dead_letters = || rt.block_on(action::list_dead_letters(&pool)).unwrap();
+        wait_for(|| !dead_letters().is_empty());
+
+        let dead_letters = dead_letters();
+        assert_eq!(dead_letters[0].attempts, 3);
+        assert_eq!(dead_letters[0].event, Event::ClipCreated);
+        assert_eq!(requests.lock().len(), 3);
+        assert!(rt
+            .block_on(action::due_deliveries(10, &pool))
+            .unwrap()
+            .is_empty());
+    }
+}
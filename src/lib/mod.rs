@@ -8,33 +8,84 @@ pub use domain::clip::Clip;
 pub use domain::clip::ClipError;
 use domain::maintenance::Maintenance;
 pub use domain::time::Time;
+use domain::webhook::Webhooks;
 
 pub use data::DataError;
 
-use data::AppDatabase;
+use data::{content::ContentStore, AppDatabase};
+use domain::clip::limits::ClipLimits;
+use rocket::data::{ByteUnit, Limits};
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
+use service::events::ClipEvents;
 use web::hit_counter::HitCounter;
+use web::metrics::Metrics;
 use web::renderer::Renderer;
+use web::visitor::VisitorKey;
+use web::PublicUrl;
+
+/// Request bodies are allowed to grow past the content limit so that encoding
+/// overhead doesn't hide the domain error behind a 413.
+fn body_limit(limits: &ClipLimits) -> ByteUnit {
+    ByteUnit::from(limits.max_content_size * 4 + 64 * 1024)
+}
+
+fn file_limit(limits: &ClipLimits) -> ByteUnit {
+    ByteUnit::from(limits.max_attachment_size * 2)
+}
 
 pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    let clip_limits = &config.limits;
+    let limits = Limits::default()
+        .limit("form", body_limit(clip_limits))
+        .limit("json", body_limit(clip_limits))
+        .limit("file", file_limit(clip_limits))
+        .limit(
+            "data-form",
+            body_limit(clip_limits) + file_limit(clip_limits),
+        );
+    let figment = rocket::Config::figment().merge(("limits", limits));
+
+    rocket::custom(figment)
         .manage::<AppDatabase>(config.database)
+        .manage::<ContentStore>(config.content_store)
+        .manage::<ClipEvents>(config.events)
         .manage::<Renderer>(config.renderer)
         .manage::<HitCounter>(config.hit_counter)
         .manage::<Maintenance>(config.maintenance)
+        .manage::<Webhooks>(config.webhooks)
+        .manage::<Metrics>(config.metrics)
+        .manage::<ClipLimits>(config.limits)
+        .manage::<PublicUrl>(config.public_url)
+        .manage::<VisitorKey>(config.visitor_key)
+        .attach(web::request_id::RequestIdFairing)
+        .attach(web::hit_counter::HitCounterFairing)
+        .attach(web::metrics::MetricsFairing)
         .mount("/", web::http::routes())
+        .mount("/", web::metrics::routes())
         .mount("/api/clip", web::api::routes())
+        .mount("/api/webhook", web::api::webhook_routes())
+        .mount("/api/collection", web::api::collection_routes())
+        .mount("/api", web::openapi::routes())
         .mount("/static", FileServer::from("static"))
         .register("/", web::http::catcher::catchers())
         .register("/api/clip", web::api::catcher::catchers())
+        .register("/api/webhook", web::api::catcher::catchers())
+        .register("/api/collection", web::api::catcher::catchers())
 }
 
 pub struct RocketConfig {
     pub renderer: Renderer<'static>,
     pub database: AppDatabase,
+    pub content_store: ContentStore,
+    pub events: ClipEvents,
     pub hit_counter: HitCounter,
     pub maintenance: Maintenance,
+    pub webhooks: Webhooks,
+    pub metrics: Metrics,
+    pub limits: ClipLimits,
+    pub public_url: PublicUrl,
+    pub visitor_key: VisitorKey,
 }
 
 #[cfg(test)]
@@ -43,7 +94,7 @@ pub mod test {
         tokio::runtime::Builder::new_multi_thread()
             .worker_threads(1)
             .max_blocking_threads(1)
-            .enable_time()
+            .enable_all()
             .build()
             .expect("failed to spawn tokio runtime")
     }
@@ -1,26 +1,142 @@
+use chrono::Utc;
+use rand::RngCore;
+use std::time::Duration;
+
 use crate::{
-    data::{query, DatabasePool, Transaction},
-    domain::Clip,
-    web::api::ApiKey,
-    ShortCode,
+    data::{content::ContentStore, query, DataError, DatabasePool, Transaction},
+    domain::{
+        clip::{
+            field,
+            stats::{ClipStats, DailyViews},
+            ClipSummary, TrashedClip,
+        },
+        collection::{Collection, CollectionError, Member, MAX_CLIPS},
+        webhook::{DeadLetter, Delivery, Event, Payload, Webhook, WebhookError},
+        Clip,
+    },
+    web::{api::ApiKey, visitor::VisitorKey},
+    ClipError, ShortCode,
 };
 
+use super::events::{ClipChange, ClipEvents};
 use super::{ask, ServiceError};
 
 type Result<T> = std::result::Result<T, ServiceError>;
 type ResultClip = Result<Clip>;
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> ResultClip {
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+/// Queues `payload` for every webhook subscribed to its event. Failing to queue
+/// is logged and never fails the action that caused the event.
+async fn publish(payload: Payload, pool: &DatabasePool) {
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(err) => {
+            eprintln!("failed to serialize webhook payload: {}", err);
+            return;
+        }
+    };
+
+    if let Err(err) = query::enqueue_deliveries(&payload.event.to_string(), &body, pool).await {
+        eprintln!("failed to queue webhook deliveries: {}", err);
+    }
 }
 
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> ResultClip {
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+async fn published(event: Event, clip: Clip, pool: &DatabasePool) -> Clip {
+    publish(Payload::for_clip(event, &clip), pool).await;
+    clip
+}
+
+pub async fn new_clip(req: ask::NewClip, store: &ContentStore, pool: &DatabasePool) -> ResultClip {
+    let clip = query::new_clip(req, store, pool).await?.try_into()?;
+    Ok(published(Event::ClipCreated, clip, pool).await)
+}
+
+/// Copies a clip the caller can read into a new clip that remembers its origin.
+pub async fn fork_clip(req: ask::GetClip, store: &ContentStore, pool: &DatabasePool) -> ResultClip {
+    let original = get_clip(req, store, pool).await?;
+    let clip = query::new_clip(ask::NewFork::from(original), store, pool)
+        .await?
+        .try_into()?;
+    Ok(published(Event::ClipCreated, clip, pool).await)
+}
+
+/// Creates every clip or, if any of them fails, none of them.
+pub async fn new_clips(
+    reqs: Vec<ask::NewClip>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Vec<Clip>> {
+    let short_codes = query::new_clips(reqs, store, pool).await?;
+
+    let mut clips = Vec::with_capacity(short_codes.len());
+    for short_code in short_codes {
+        let clip = query::get_clip(short_code, store, pool).await?.try_into()?;
+        clips.push(published(Event::ClipCreated, clip, pool).await);
+    }
+
+    Ok(clips)
+}
+
+pub async fn new_clip_with_attachment(
+    req: ask::NewClip,
+    attachment: ask::NewAttachment,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> ResultClip {
+    let clip = query::new_clip_with_attachment(req, attachment, store, pool)
+        .await?
+        .try_into()?;
+    Ok(published(Event::ClipCreated, clip, pool).await)
 }
 
-pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> ResultClip {
+/// Versions too large to store can never match, so they are dropped.
+fn expected_versions(if_match: Option<&[field::Version]>) -> Option<Vec<i64>> {
+    if_match.map(|versions| {
+        versions
+            .iter()
+            .filter_map(|version| i64::try_from(version.into_inner()).ok())
+            .collect()
+    })
+}
+
+/// Replaces a clip. With `if_match`, fails unless the stored version is one of them.
+pub async fn update_clip(
+    req: ask::UpdateClip,
+    if_match: Option<&[field::Version]>,
+    events: &ClipEvents,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> ResultClip {
+    let if_match = expected_versions(if_match);
+    let clip: Clip = query::update_clip(req, if_match.as_deref(), store, pool)
+        .await?
+        .try_into()?;
+    events.publish(ClipChange::Updated(Box::new(clip.clone())));
+    Ok(published(Event::ClipUpdated, clip, pool).await)
+}
+
+pub async fn patch_clip(
+    short_code: ShortCode,
+    req: ask::PatchClip,
+    if_match: Option<&[field::Version]>,
+    events: &ClipEvents,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> ResultClip {
+    if let ask::Patch::Clear = req.content {
+        return Err(ClipError::EmptyContent.into());
+    }
+
+    let if_match = expected_versions(if_match);
+    let clip: Clip = query::patch_clip((short_code, req), if_match.as_deref(), store, pool)
+        .await?
+        .try_into()?;
+    events.publish(ClipChange::Updated(Box::new(clip.clone())));
+    Ok(published(Event::ClipUpdated, clip, pool).await)
+}
+
+pub async fn get_clip(req: ask::GetClip, store: &ContentStore, pool: &DatabasePool) -> ResultClip {
     let user_password = req.password.clone();
-    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
+    let clip: Clip = query::get_clip(req, store, pool).await?.try_into()?;
 
     if clip.password.has_password() {
         if clip.password == user_password {
@@ -33,12 +149,182 @@ pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> ResultClip {
     }
 }
 
-pub async fn increase_hit_count(
-    short_code: &ShortCode,
-    hits: u32,
+/// Returns the file attached to a clip, enforcing the clip's password.
+pub async fn get_attachment(
+    req: ask::GetClip,
+    store: &ContentStore,
     pool: &DatabasePool,
-) -> Result<()> {
-    Ok(query::increase_hit_count(short_code, hits, pool).await?)
+) -> Result<(field::Attachment, Vec<u8>)> {
+    let short_code = req.short_code.clone();
+    get_clip(req, store, pool).await?;
+
+    Ok(query::get_attachment(short_code, store, pool)
+        .await?
+        .try_into()?)
+}
+
+/// Commits a batch of hits in one transaction, then announces the clips that
+/// were viewed for the first time.
+pub async fn increase_hit_counts(views: Vec<ask::NewViews>, pool: &DatabasePool) -> Result<()> {
+    let counts: Vec<(ShortCode, u32)> = views
+        .iter()
+        .map(|views| (views.short_code.clone(), views.count))
+        .collect();
+
+    let mut transaction = begin_transaction(pool).await?;
+    let totals = query::increase_hit_counts(views, &mut transaction).await?;
+    end_transaction(transaction).await?;
+
+    for ((short_code, count), total) in counts.into_iter().zip(totals) {
+        if total == Some(i64::from(count)) {
+            publish(
+                Payload::new(Event::ClipFirstViewed, short_code.clone()),
+                pool,
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Views of a clip per day over the last `days` days. Protected clips need
+/// their password.
+pub async fn get_clip_stats(
+    req: ask::GetClip,
+    days: u32,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<ClipStats> {
+    let clip = get_clip(req, store, pool).await?;
+    clip_stats(&clip, days, pool).await
+}
+
+/// Views of a clip that has already been read, per day over the last `days` days.
+pub async fn clip_stats(clip: &Clip, days: u32, pool: &DatabasePool) -> Result<ClipStats> {
+    let today = Utc::now().date_naive();
+    let since = ClipStats::first_day(days, today);
+    let recorded = query::get_clip_views(clip.short_code.as_str(), since, pool)
+        .await?
+        .into_iter()
+        .map(DailyViews::try_from)
+        .collect::<std::result::Result<Vec<_>, ClipError>>()?;
+
+    Ok(ClipStats::new(
+        clip.short_code.clone(),
+        clip.hits.clone(),
+        recorded,
+        days,
+        today,
+    ))
+}
+
+/// Fetches each clip on its own, so one missing or locked clip does not fail the rest.
+pub async fn get_clips(
+    reqs: Vec<ask::GetClip>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Vec<ResultClip> {
+    let mut clips = Vec::with_capacity(reqs.len());
+    for req in reqs {
+        clips.push(get_clip(req, store, pool).await);
+    }
+
+    clips
+}
+
+/// Lists the newest clips, only those tagged `tag` when one is given.
+pub async fn list_clips(
+    tag: Option<&str>,
+    limit: u32,
+    pool: &DatabasePool,
+) -> Result<Vec<ClipSummary>> {
+    let tag = tag.map(field::Tags::normalize).transpose()?;
+    let tag = tag.as_deref().filter(|tag| !tag.is_empty());
+
+    query::list_clips(tag, limit, pool)
+        .await?
+        .into_iter()
+        .map(|clip| Ok(clip.try_into()?))
+        .collect()
+}
+
+fn check_collection_clips(clips: &[ShortCode]) -> Result<()> {
+    if clips.is_empty() {
+        return Err(CollectionError::Empty.into());
+    }
+    if clips.len() > MAX_CLIPS {
+        return Err(CollectionError::TooManyClips(MAX_CLIPS).into());
+    }
+    for (i, short_code) in clips.iter().enumerate() {
+        if clips[..i].contains(short_code) {
+            return Err(CollectionError::DuplicateClip(short_code.as_str().to_owned()).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads a collection with previews of its clips. Clips deleted while the
+/// collection is being loaded are left out.
+async fn load_collection(
+    short_code: &str,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Collection> {
+    let collection = query::get_collection(short_code, pool).await?;
+
+    let mut members = Vec::new();
+    for short_code in query::get_collection_clips(short_code, pool).await? {
+        match query::get_clip(short_code, store, pool).await {
+            Ok(clip) => members.push(Member::from(Clip::try_from(clip)?)),
+            Err(DataError::Database(sqlx::Error::RowNotFound)) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok((collection, members).try_into()?)
+}
+
+pub async fn new_collection(
+    req: ask::NewCollection,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Collection> {
+    check_collection_clips(&req.clips)?;
+    let short_code = query::new_collection(req, pool).await?;
+
+    load_collection(&short_code, store, pool).await
+}
+
+pub async fn get_collection(
+    req: ask::GetCollection,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Collection> {
+    let collection = load_collection(req.short_code.as_str(), store, pool).await?;
+
+    if collection.password.has_password() && collection.password != req.password {
+        return Err(ServiceError::PermissionError("invalid password".to_owned()));
+    }
+
+    Ok(collection)
+}
+
+/// Replaces the clips of a collection with `clips`, in that order. Protected
+/// collections need their password, as when reading them.
+pub async fn set_collection_clips(
+    req: ask::GetCollection,
+    clips: Vec<ShortCode>,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<Collection> {
+    let short_code = get_collection(req, store, pool).await?.short_code;
+    check_collection_clips(&clips)?;
+    let clips: Vec<String> = clips.into_iter().map(ShortCode::into_inner).collect();
+    query::set_collection_clips(short_code.as_str(), &clips, pool).await?;
+
+    load_collection(short_code.as_str(), store, pool).await
 }
 
 pub async fn begin_transaction(pool: &DatabasePool) -> Result<Transaction<'_>> {
@@ -66,6 +352,167 @@ pub async fn is_valid_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<bo
     Ok(query::is_valid_api_key(api_key, pool).await?)
 }
 
-pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
-    Ok(query::delete_expired(pool).await?)
+/// Moves expired clips to the trash. They can be restored until purged.
+pub async fn trash_expired(events: &ClipEvents, pool: &DatabasePool) -> Result<u64> {
+    let expired = query::trash_expired(pool).await?;
+    let count = expired.len() as u64;
+    for short_code in expired {
+        let short_code = ShortCode::from(short_code);
+        events.publish(ClipChange::Deleted(short_code.clone()));
+        publish(Payload::new(Event::ClipExpired, short_code), pool).await;
+    }
+
+    Ok(count)
+}
+
+/// Deletes clips that have been in the trash for longer than `grace_period`.
+pub async fn purge_trash(grace_period: Duration, pool: &DatabasePool) -> Result<u64> {
+    let grace_period = i64::try_from(grace_period.as_secs()).unwrap_or(i64::MAX);
+    let cutoff = Utc::now().timestamp().saturating_sub(grace_period);
+
+    Ok(query::purge_trash(cutoff, pool).await?.len() as u64)
+}
+
+pub async fn list_trash(pool: &DatabasePool) -> Result<Vec<TrashedClip>> {
+    Ok(query::list_trash(pool)
+        .await?
+        .into_iter()
+        .map(TrashedClip::from)
+        .collect())
+}
+
+/// Takes a clip out of the trash. Protected clips need their password.
+pub async fn restore_clip(
+    req: ask::GetClip,
+    events: &ClipEvents,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> ResultClip {
+    let password = query::get_trashed_password(req.short_code.as_str(), pool).await?;
+    let password = field::Password::new(password.unwrap_or_default())?;
+    if password.has_password() && password != req.password {
+        return Err(ServiceError::PermissionError("invalid password".to_owned()));
+    }
+
+    let clip: Clip = query::restore_clip(req.short_code.as_str(), store, pool)
+        .await?
+        .try_into()?;
+    events.publish(ClipChange::Updated(Box::new(clip.clone())));
+    Ok(published(Event::ClipUpdated, clip, pool).await)
+}
+
+/// The key visitor hashes are salted with. It is created once and kept in the
+/// database, so it survives restarts.
+pub async fn visitor_key(pool: &DatabasePool) -> Result<VisitorKey> {
+    let mut secret = [0; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let secret = query::get_or_insert_secret("visitor_key", &secret, pool).await?;
+
+    Ok(VisitorKey::new(secret))
+}
+
+/// Forgets who viewed clips on earlier days. Visitors are only needed to
+/// count unique views within a day.
+pub async fn forget_visitors(pool: &DatabasePool) -> Result<u64> {
+    let today = Utc::now().date_naive();
+    Ok(query::delete_visitors_before(today, pool).await?)
+}
+
+pub async fn delete_unreferenced_contents(
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<u64> {
+    Ok(query::delete_unreferenced_contents(store, pool).await?)
+}
+
+pub async fn recompress_contents(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    Ok(query::recompress_contents(store, pool).await?)
+}
+
+pub async fn detect_languages(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    Ok(query::detect_languages(store, pool).await?)
+}
+
+pub async fn rehash_legacy_contents(store: &ContentStore, pool: &DatabasePool) -> Result<u64> {
+    Ok(query::rehash_legacy_contents(store, pool).await?)
+}
+
+pub async fn check_blobs(
+    remove_orphans: bool,
+    store: &ContentStore,
+    pool: &DatabasePool,
+) -> Result<query::BlobReport> {
+    Ok(query::check_blobs(remove_orphans, store, pool).await?)
+}
+
+pub async fn new_webhook(req: ask::NewWebhook, pool: &DatabasePool) -> Result<Webhook> {
+    match reqwest::Url::parse(&req.url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => (),
+        _ => return Err(WebhookError::InvalidUrl(req.url).into()),
+    }
+
+    Ok(query::new_webhook(req, pool).await?.try_into()?)
+}
+
+/// Lists webhooks without their secrets.
+pub async fn list_webhooks(pool: &DatabasePool) -> Result<Vec<Webhook>> {
+    query::list_webhooks(pool)
+        .await?
+        .into_iter()
+        .map(|webhook| {
+            let mut webhook = Webhook::try_from(webhook)?;
+            webhook.secret = None;
+            Ok(webhook)
+        })
+        .collect()
+}
+
+pub async fn delete_webhook(webhook_id: &str, pool: &DatabasePool) -> Result<()> {
+    Ok(query::delete_webhook(webhook_id, pool).await?)
+}
+
+pub async fn due_deliveries(limit: u32, pool: &DatabasePool) -> Result<Vec<Delivery>> {
+    query::due_deliveries(limit, pool)
+        .await?
+        .into_iter()
+        .map(|delivery| Ok(delivery.try_into()?))
+        .collect()
+}
+
+pub async fn complete_delivery(delivery_id: &str, pool: &DatabasePool) -> Result<()> {
+    Ok(query::complete_delivery(delivery_id, pool).await?)
+}
+
+pub async fn retry_delivery(
+    delivery_id: &str,
+    attempts: u32,
+    error: &str,
+    retry_at: chrono::DateTime<chrono::Utc>,
+    pool: &DatabasePool,
+) -> Result<()> {
+    Ok(query::retry_delivery(
+        delivery_id,
+        i64::from(attempts),
+        error,
+        retry_at.timestamp(),
+        pool,
+    )
+    .await?)
+}
+
+pub async fn dead_letter_delivery(
+    delivery_id: &str,
+    attempts: u32,
+    error: &str,
+    pool: &DatabasePool,
+) -> Result<()> {
+    Ok(query::dead_letter_delivery(delivery_id, i64::from(attempts), error, pool).await?)
+}
+
+pub async fn list_dead_letters(pool: &DatabasePool) -> Result<Vec<DeadLetter>> {
+    query::list_dead_letters(pool)
+        .await?
+        .into_iter()
+        .map(|letter| Ok(letter.try_into()?))
+        .collect()
 }
@@ -1,6 +1,11 @@
-use serde::{Deserialize, Serialize};
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::{domain::clip::field, ShortCode};
+use crate::{
+    domain::clip::{field, limits::ClipLimits},
+    domain::webhook::Event,
+    ClipError, ShortCode,
+};
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct NewClip {
@@ -8,6 +13,96 @@ pub struct NewClip {
     pub title: field::Title,
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
+    #[serde(default)]
+    pub tags: field::Tags,
+    #[serde(default)]
+    pub language: field::Language,
+}
+
+impl NewClip {
+    /// Checks the clip against the server's size limits.
+    pub fn check(&self, limits: &ClipLimits) -> Result<(), ClipError> {
+        limits.check_content(&self.content)?;
+        limits.check_title(&self.title)
+    }
+}
+
+/// A new clip copied from the clip at `forked_from`.
+#[derive(Debug)]
+pub struct NewFork {
+    pub forked_from: ShortCode,
+    pub clip: NewClip,
+}
+
+impl From<crate::Clip> for NewFork {
+    /// The fork keeps the password and expiry date, so copying a clip never
+    /// makes it public or permanent.
+    fn from(clip: crate::Clip) -> Self {
+        Self {
+            forked_from: clip.short_code,
+            clip: NewClip {
+                content: clip.content,
+                title: clip.title,
+                expires_at: clip.expires_at,
+                password: clip.password,
+                tags: clip.tags,
+                language: clip.language,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NewAttachment {
+    pub(crate) file_name: String,
+    pub(crate) content_type: String,
+    pub(crate) content: Vec<u8>,
+}
+
+impl NewAttachment {
+    pub fn new(file_name: &str, content_type: &str, content: Vec<u8>) -> Result<Self, ClipError> {
+        if content.is_empty() {
+            return Err(ClipError::EmptyAttachment);
+        }
+
+        let content_type = match content_type.trim() {
+            "" => "application/octet-stream",
+            content_type => content_type,
+        };
+
+        Ok(Self {
+            file_name: Self::sanitize_file_name(file_name),
+            content_type: content_type.to_owned(),
+            content,
+        })
+    }
+
+    pub fn check(&self, limits: &ClipLimits) -> Result<(), ClipError> {
+        limits.check_attachment(self.content.len())
+    }
+
+    /// Clips posted with only a file use the file name as their content.
+    pub fn default_content(&self) -> Result<field::Content, ClipError> {
+        field::Content::new(&self.file_name)
+    }
+
+    fn sanitize_file_name(file_name: &str) -> String {
+        let file_name: String = file_name
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' | ' ' => c,
+                _ => '_',
+            })
+            .collect();
+
+        match file_name.trim().trim_start_matches('.') {
+            "" => "attachment".to_owned(),
+            file_name => file_name.to_owned(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -17,11 +112,118 @@ pub struct UpdateClip {
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
     pub short_code: field::ShortCode,
+    #[serde(default)]
+    pub language: field::Language,
+}
+
+impl UpdateClip {
+    pub fn check(&self, limits: &ClipLimits) -> Result<(), ClipError> {
+        limits.check_content(&self.content)?;
+        limits.check_title(&self.title)
+    }
+}
+
+/// A field of a partial update. Leaving the field out keeps the current
+/// value and `null` clears it.
+#[derive(Clone, Debug, Default)]
+pub enum Patch<T> {
+    #[default]
+    Keep,
+    Clear,
+    Set(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_keep(&self) -> bool {
+        matches!(self, Self::Keep)
+    }
+
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Patch<U> {
+        match self {
+            Self::Keep => Patch::Keep,
+            Self::Clear => Patch::Clear,
+            Self::Set(value) => Patch::Set(f(value)),
+        }
+    }
+
+    pub fn try_map<U, E, F: FnOnce(T) -> Result<U, E>>(self, f: F) -> Result<Patch<U>, E> {
+        Ok(match self {
+            Self::Keep => Patch::Keep,
+            Self::Clear => Patch::Clear,
+            Self::Set(value) => Patch::Set(f(value)?),
+        })
+    }
+
+    /// Returns `None` to keep the value, or the value to store otherwise.
+    pub fn into_change<U, F: FnOnce(T) -> Option<U>>(self, f: F) -> Option<Option<U>> {
+        match self {
+            Self::Keep => None,
+            Self::Clear => Some(None),
+            Self::Set(value) => Some(f(value)),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Self::Set(value),
+            None => Self::Clear,
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Set(value) => serializer.serialize_some(value),
+            _ => serializer.serialize_none(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PatchClip {
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub content: Patch<field::Content>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub title: Patch<field::Title>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub expires_at: Patch<field::ExpiresAt>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub password: Patch<field::Password>,
+    #[serde(default, skip_serializing_if = "Patch::is_keep")]
+    pub language: Patch<field::Language>,
+}
+
+impl PatchClip {
+    /// Checks the fields being set against the server's size limits.
+    pub fn check(&self, limits: &ClipLimits) -> Result<(), ClipError> {
+        if let Patch::Set(content) = &self.content {
+            limits.check_content(content)?;
+        }
+        if let Patch::Set(title) = &self.title {
+            limits.check_title(title)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewWebhook {
+    pub url: String,
+    /// Generated when left out.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Every event when empty.
+    #[serde(default)]
+    pub events: Vec<Event>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetClip {
     pub short_code: ShortCode,
+    #[serde(default)]
     pub password: field::Password,
 }
 
@@ -34,6 +236,16 @@ impl GetClip {
     }
 }
 
+/// Views of a clip on one day, committed together. `visitors` are hashes of
+/// who viewed it, to count unique views.
+#[derive(Clone, Debug)]
+pub struct NewViews {
+    pub short_code: ShortCode,
+    pub day: NaiveDate,
+    pub count: u32,
+    pub visitors: Vec<String>,
+}
+
 impl From<ShortCode> for GetClip {
     fn from(short_code: ShortCode) -> Self {
         Self {
@@ -48,3 +260,46 @@ impl From<&str> for GetClip {
         Self::from_raw(raw)
     }
 }
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct NewCollection {
+    #[serde(default)]
+    pub title: field::Title,
+    #[serde(default)]
+    pub password: field::Password,
+    /// Member clips, in the order they are listed.
+    pub clips: Vec<ShortCode>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetCollection {
+    pub short_code: ShortCode,
+    #[serde(default)]
+    pub password: field::Password,
+}
+
+impl From<ShortCode> for GetCollection {
+    fn from(short_code: ShortCode) -> Self {
+        Self {
+            short_code,
+            password: field::Password::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// A request for a clip with `content` and nothing else set.
+    pub fn new_clip(content: &str) -> NewClip {
+        NewClip {
+            content: field::Content::new(content).unwrap(),
+            title: field::Title::default(),
+            expires_at: field::ExpiresAt::default(),
+            password: field::Password::default(),
+            tags: field::Tags::default(),
+            language: field::Language::default(),
+        }
+    }
+}
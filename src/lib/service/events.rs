@@ -0,0 +1,94 @@
+use tokio::sync::broadcast;
+
+use crate::{Clip, ShortCode};
+
+/// Changes a slow subscriber may fall behind by before it starts missing them.
+const CAPACITY: usize = 256;
+
+/// A change to a clip, pushed to everyone watching it live.
+#[derive(Clone, Debug)]
+pub enum ClipChange {
+    Updated(Box<Clip>),
+    Deleted(ShortCode),
+}
+
+impl ClipChange {
+    pub fn short_code(&self) -> &ShortCode {
+        match self {
+            Self::Updated(clip) => &clip.short_code,
+            Self::Deleted(short_code) => short_code,
+        }
+    }
+}
+
+/// The channel clip changes are broadcast on. It is managed by Rocket; clones
+/// share the same channel.
+#[derive(Clone, Debug)]
+pub struct ClipEvents(broadcast::Sender<ClipChange>);
+
+impl Default for ClipEvents {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl ClipEvents {
+    pub fn publish(&self, change: ClipChange) {
+        // Sending only fails when nobody is watching.
+        let _ = self.0.send(change);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ClipChange> {
+        self.0.subscribe()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::test::new_db;
+    use crate::domain::clip::field;
+    use crate::service::{action, ask};
+    use crate::test::async_runtime;
+
+    #[test]
+    fn updates_are_published() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let events = ClipEvents::default();
+
+        rt.block_on(async move {
+            let req = ask::test::new_clip("before");
+            let clip = action::new_clip(req, &Default::default(), pool)
+                .await
+                .unwrap();
+
+            let mut changes = events.subscribe();
+            let req = ask::PatchClip {
+                content: ask::Patch::Set(field::Content::new("after").unwrap()),
+                ..Default::default()
+            };
+            action::patch_clip(
+                clip.short_code.clone(),
+                req,
+                None,
+                &events,
+                &Default::default(),
+                pool,
+            )
+            .await
+            .unwrap();
+
+            loop {
+                match changes.recv().await.unwrap() {
+                    ClipChange::Updated(updated) if updated.short_code == clip.short_code => {
+                        assert_eq!(updated.content.as_str(), "after");
+                        break;
+                    }
+                    _ => continue,
+                }
+            }
+        });
+    }
+}
@@ -1,6 +1,9 @@
 pub mod action;
 pub mod ask;
+pub mod events;
 
+use crate::domain::collection::CollectionError;
+use crate::domain::webhook::WebhookError;
 use crate::{ClipError, DataError};
 
 #[derive(Debug, thiserror::Error)]
@@ -16,15 +19,24 @@ pub enum ServiceError {
 
     #[error("permission error: {0}")]
     PermissionError(String),
+
+    #[error("clip version does not match")]
+    VersionMismatch,
+
+    #[error("webhook error: {0}")]
+    Webhook(#[from] WebhookError),
+
+    #[error("collection error: {0}")]
+    Collection(#[from] CollectionError),
 }
 
 impl From<DataError> for ServiceError {
     fn from(err: DataError) -> Self {
         match err {
-            DataError::Database(d) => match d {
-                sqlx::Error::RowNotFound => Self::NotFound,
-                other => Self::Data(DataError::Database(other)),
-            },
+            DataError::Database(sqlx::Error::RowNotFound) => Self::NotFound,
+            DataError::VersionMismatch => Self::VersionMismatch,
+            DataError::MissingClip(short_code) => CollectionError::UnknownClip(short_code).into(),
+            other => Self::Data(other),
         }
     }
 }
@@ -2,32 +2,46 @@ use std::str::FromStr;
 
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
 use rocket::{
+    form::{Errors, Form},
     http::{CookieJar, Status},
     request::{FromRequest, Outcome},
-    serde::json::Json,
-    Responder, State,
+    response::{self, status, Responder},
+    serde::json::{self, Json},
+    Request, State,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    data::AppDatabase,
-    service::{self, action, ServiceError},
+    data::{content::ContentStore, AppDatabase},
+    domain::clip::{field, limits::ClipLimits, stats, ClipSummary, TrashedClip},
+    domain::collection::{Collection, CollectionError},
+    domain::webhook::{DeadLetter, Webhook, WebhookError},
+    service::{self, action, ask::Patch, events::ClipEvents, ServiceError},
     web::PASSWORD_COOKIE,
-    Clip,
+    Clip, ClipError, ShortCode,
 };
 
+use super::etag::{IfMatch, IfNoneMatch, Tagged};
+use super::form::{self, UploadError};
 use super::hit_counter::HitCounter;
+use super::metrics::Metrics;
+use super::request_id::RequestId;
+use super::visitor::Visitor;
 
 pub const API_KEY_HEADER: &str = "x-api-key";
 
-#[derive(Responder, Debug, thiserror::Error, Serialize)]
+/// Most clips a single batch request may create or fetch.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// Most clips a single listing returns.
+pub const MAX_LIST_SIZE: u32 = 100;
+
+#[derive(Debug, thiserror::Error)]
 pub enum ApiKeyError {
     #[error("API key not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(String),
+    NotFound,
 
-    #[error("invalid API key format")]
-    #[response(status = 400, content_type = "json")]
+    #[error("invalid API key format: {0}")]
     DecodeError(String),
 }
 
@@ -62,71 +76,246 @@ impl FromStr for ApiKey {
     }
 }
 
-#[derive(Responder, Debug, thiserror::Error)]
-pub enum ApiError {
-    #[error("not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(Json<String>),
+/// The body of every error response from the API.
+#[derive(Debug, Clone, Deserialize, Serialize, thiserror::Error)]
+#[error("{message} ({code}, request {request_id})")]
+pub struct ErrorBody {
+    /// Stable, machine readable error code such as `content_too_large`.
+    pub code: String,
+    pub message: String,
+    /// The request field the error is about, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub request_id: String,
+}
 
-    #[error("server error")]
-    #[response(status = 500, content_type = "json")]
-    Server(Json<String>),
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{message}")]
+pub struct ApiError {
+    status: Status,
+    code: String,
+    message: String,
+    field: Option<String>,
+}
 
-    #[error("client error")]
-    #[response(status = 400, content_type = "json")]
-    User(Json<String>),
+impl ApiError {
+    pub fn new<C: Into<String>, M: Into<String>>(status: Status, code: C, message: M) -> Self {
+        Self {
+            status,
+            code: code.into(),
+            message: message.into(),
+            field: None,
+        }
+    }
 
-    #[error("key error")]
-    #[response(status = 400, content_type = "json")]
-    KeyError(Json<ApiKeyError>),
+    pub fn with_field<F: Into<String>>(mut self, field: F) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+
+    pub fn server() -> Self {
+        Self::new(
+            Status::InternalServerError,
+            "server_error",
+            "a server error occurred",
+        )
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn into_body(self, request_id: RequestId) -> ErrorBody {
+        ErrorBody {
+            code: self.code,
+            message: self.message,
+            field: self.field,
+            request_id: request_id.into_inner(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = self.status;
+        let body = self.into_body(RequestId::of(req));
+        status::Custom(status, Json(body)).respond_to(req)
+    }
+}
+
+impl From<ClipError> for ApiError {
+    fn from(err: ClipError) -> Self {
+        let (code, field) = match &err {
+            ClipError::InvalidPassword(_) => ("invalid_password", "password"),
+            ClipError::InvalidTitle(_) => ("invalid_title", "title"),
+            ClipError::TitleTooLong(_) => ("title_too_long", "title"),
+            ClipError::InvalidTag(_) => ("invalid_tag", "tags"),
+            ClipError::TooManyTags(_) => ("too_many_tags", "tags"),
+            ClipError::UnknownLanguage(_) => ("unknown_language", "language"),
+            ClipError::EmptyContent => ("empty_content", "content"),
+            ClipError::ContentTooLarge(_) => ("content_too_large", "content"),
+            ClipError::EmptyAttachment => ("empty_attachment", "attachment"),
+            ClipError::AttachmentTooLarge(_) => ("attachment_too_large", "attachment"),
+            ClipError::InvalidDate(_) | ClipError::DateParse(_) => ("invalid_date", "expires_at"),
+            ClipError::ContentDecode(_) | ClipError::Id(_) | ClipError::Hits(_) => {
+                eprintln!("failed to load clip: {}", err);
+                return Self::server();
+            }
+        };
+
+        Self::new(Status::BadRequest, code, err.to_string()).with_field(field)
+    }
 }
 
 impl From<ServiceError> for ApiError {
     fn from(err: ServiceError) -> Self {
         match err {
-            ServiceError::Clip(err) => Self::User(Json(format!("clip parsing error: {}", err))),
-            ServiceError::NotFound => Self::NotFound(Json("not found".to_owned())),
-            ServiceError::Data(_) => Self::Server(Json("a server error occurred".to_owned())),
-            ServiceError::PermissionError(err) => Self::User(Json(err)),
+            ServiceError::Clip(err) => err.into(),
+            ServiceError::NotFound => Self::new(Status::NotFound, "not_found", "clip not found"),
+            ServiceError::Data(err) => {
+                eprintln!("database error: {}", err);
+                Self::server()
+            }
+            ServiceError::PermissionError(err) => {
+                Self::new(Status::Unauthorized, "permission_denied", err).with_field("password")
+            }
+            ServiceError::VersionMismatch => Self::new(
+                Status::PreconditionFailed,
+                "version_mismatch",
+                "clip was modified since it was last read",
+            ),
+            ServiceError::Webhook(err) => err.into(),
+            ServiceError::Collection(err) => err.into(),
         }
     }
 }
 
-#[rocket::async_trait]
-impl<'r> FromRequest<'r> for ApiKey {
-    type Error = ApiError;
+impl From<CollectionError> for ApiError {
+    fn from(err: CollectionError) -> Self {
+        let code = match err {
+            CollectionError::Empty => "empty_collection",
+            CollectionError::TooManyClips(_) => "too_many_clips",
+            CollectionError::DuplicateClip(_) => "duplicate_clip",
+            CollectionError::UnknownClip(_) => "unknown_clip",
+        };
 
-    async fn from_request(req: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
-        fn server_error() -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((
-                Status::InternalServerError,
-                ApiError::Server(Json("server error".to_owned())),
-            ))
+        Self::new(Status::BadRequest, code, err.to_string()).with_field("clips")
+    }
+}
+
+impl From<WebhookError> for ApiError {
+    fn from(err: WebhookError) -> Self {
+        match err {
+            WebhookError::InvalidUrl(_) => {
+                Self::new(Status::BadRequest, "invalid_url", err.to_string()).with_field("url")
+            }
+            WebhookError::UnknownEvent(_) => {
+                eprintln!("failed to load webhook: {}", err);
+                Self::server()
+            }
+        }
+    }
+}
+
+impl From<ApiKeyError> for ApiError {
+    fn from(err: ApiKeyError) -> Self {
+        let code = match err {
+            ApiKeyError::NotFound => "api_key_not_found",
+            ApiKeyError::DecodeError(_) => "invalid_api_key",
+        };
+
+        Self::new(Status::BadRequest, code, err.to_string())
+    }
+}
+
+impl From<json::Error<'_>> for ApiError {
+    fn from(err: json::Error<'_>) -> Self {
+        match err {
+            json::Error::Io(err) => Self::new(
+                Status::BadRequest,
+                "invalid_request",
+                format!("failed to read request: {}", err),
+            ),
+            json::Error::Parse(_, err) => Self::new(
+                Status::BadRequest,
+                "invalid_json",
+                format!("clip parsing error: {}", err),
+            ),
         }
+    }
+}
+
+impl From<Errors<'_>> for ApiError {
+    fn from(errs: Errors<'_>) -> Self {
+        let field = errs
+            .iter()
+            .find_map(|err| err.name.as_ref().map(|name| name.to_string()));
+        let err = Self::new(
+            Status::BadRequest,
+            "invalid_form",
+            format!("invalid upload: {}", errs),
+        );
 
-        fn key_error(err: ApiKeyError) -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((Status::BadRequest, ApiError::KeyError(Json(err))))
+        match field {
+            Some(field) => err.with_field(field),
+            None => err,
         }
+    }
+}
+
+/// Outcome of one clip in a batch fetch: either the clip or why it could not be read.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BatchItem {
+    pub short_code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clip: Option<Clip>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+}
+
+fn check_batch_size(len: usize) -> Result<(), ApiError> {
+    if len > MAX_BATCH_SIZE {
+        return Err(ApiError::new(
+            Status::BadRequest,
+            "batch_too_large",
+            format!("a batch may contain at most {} clips", MAX_BATCH_SIZE),
+        ));
+    }
 
+    Ok(())
+}
+
+/// Request guards can only hand a status to the catchers, so the error they
+/// failed with is stashed here for the catcher to render.
+struct GuardError(Option<ApiError>);
+
+fn guard_error<T>(req: &Request<'_>, err: ApiError) -> Outcome<T, ApiError> {
+    req.local_cache(|| GuardError(Some(err.clone())));
+    Outcome::Error((err.status(), err))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r rocket::Request<'_>) -> Outcome<Self, Self::Error> {
         match req.headers().get_one(API_KEY_HEADER) {
-            None => key_error(ApiKeyError::NotFound("API key not found".to_owned())),
+            None => guard_error(req, ApiKeyError::NotFound.into()),
             Some(key) => {
                 let db = match req.guard::<&State<AppDatabase>>().await {
                     Outcome::Success(db) => db,
-                    _ => return server_error(),
+                    _ => return guard_error(req, ApiError::server()),
                 };
 
                 let api_key = match ApiKey::from_str(key) {
                     Ok(key) => key,
-                    Err(err) => return key_error(err),
+                    Err(err) => return guard_error(req, err.into()),
                 };
 
                 match action::is_valid_api_key(api_key.clone(), db.get_pool()).await {
-                    Ok(valid) if valid => Outcome::Success(api_key),
-                    Ok(valid) if !valid => {
-                        key_error(ApiKeyError::NotFound("API key not found".to_owned()))
-                    }
-                    _ => server_error(),
+                    Ok(true) => Outcome::Success(api_key),
+                    Ok(false) => guard_error(req, ApiKeyError::NotFound.into()),
+                    Err(err) => guard_error(req, err.into()),
                 }
             }
         }
@@ -143,11 +332,45 @@ pub async fn new_api_key(database: &State<AppDatabase>) -> Result<Json<&str>, Ap
 }
 
 #[rocket::get("/<short_code>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn get_clip(
     short_code: &str,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
     cookies: &CookieJar<'_>,
     hit_counter: &State<HitCounter>,
+    visitor: Visitor,
+    if_none_match: IfNoneMatch,
+    _api_key: ApiKey,
+) -> Result<Tagged<Json<Clip>>, ApiError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.into(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_owned()).ok())
+            .unwrap_or_default(),
+    };
+
+    let clip = action::get_clip(req, store, database.get_pool()).await?;
+    let response = Tagged::or_not_modified(clip.version, &if_none_match, || Json(clip));
+    if let Tagged::Fresh(..) = response {
+        hit_counter.hit(short_code.into(), visitor);
+    }
+
+    Ok(response)
+}
+
+/// Forks a clip; protected clips need their password cookie, like `get_clip`.
+#[rocket::post("/<short_code>/fork")]
+pub async fn fork_clip(
+    short_code: &str,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    metrics: &State<Metrics>,
+    cookies: &CookieJar<'_>,
     _api_key: ApiKey,
 ) -> Result<Json<Clip>, ApiError> {
     use crate::domain::clip::field::Password;
@@ -157,73 +380,536 @@ pub async fn get_clip(
         password: cookies
             .get(PASSWORD_COOKIE)
             .map(|c| c.value())
-            .map(|raw_password| Password::new(raw_password.to_owned()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+            .and_then(|raw_password| Password::new(raw_password.to_owned()).ok())
+            .unwrap_or_default(),
+    };
+    let clip = action::fork_clip(req, store, database.get_pool()).await?;
+    metrics.count_created(1);
+
+    Ok(Json(clip))
+}
+
+/// Views per day over the last `days` days, 30 unless given.
+#[rocket::get("/<short_code>/stats?<days>")]
+pub async fn get_clip_stats(
+    short_code: &str,
+    days: Option<u32>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    cookies: &CookieJar<'_>,
+    _api_key: ApiKey,
+) -> Result<Json<stats::ClipStats>, ApiError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.into(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_owned()).ok())
+            .unwrap_or_default(),
     };
+    let days = days.unwrap_or(stats::DEFAULT_DAYS);
+    let stats = action::get_clip_stats(req, days, store, database.get_pool()).await?;
 
-    let clip = action::get_clip(req, database.get_pool()).await?;
-    hit_counter.hit(short_code.into(), 1);
+    Ok(Json(stats))
+}
+
+#[rocket::get("/trash")]
+pub async fn list_trash(
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<TrashedClip>>, ApiError> {
+    Ok(Json(action::list_trash(database.get_pool()).await?))
+}
+
+/// Restores a trashed clip without an expiry date. Protected clips need
+/// their password cookie.
+#[rocket::post("/<short_code>/restore")]
+pub async fn restore_clip(
+    short_code: &str,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    events: &State<ClipEvents>,
+    cookies: &CookieJar<'_>,
+    _api_key: ApiKey,
+) -> Result<Json<Clip>, ApiError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.into(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_owned()).ok())
+            .unwrap_or_default(),
+    };
+    let clip = action::restore_clip(req, events, store, database.get_pool())
+        .await
+        .map_err(|err| match err {
+            ServiceError::NotFound => {
+                ApiError::new(Status::NotFound, "not_found", "clip not in trash")
+            }
+            err => err.into(),
+        })?;
 
     Ok(Json(clip))
 }
 
+#[rocket::get("/?<tag>&<limit>")]
+pub async fn list_clips(
+    tag: Option<&str>,
+    limit: Option<u32>,
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<ClipSummary>>, ApiError> {
+    let limit = limit.unwrap_or(MAX_LIST_SIZE).min(MAX_LIST_SIZE);
+    let clips = action::list_clips(tag, limit, database.get_pool()).await?;
+
+    Ok(Json(clips))
+}
+
+/// Body of a new clip. Fields are taken as plain strings and validated
+/// afterwards, so a bad value is reported with its own error code instead of
+/// as unparsable JSON.
+#[derive(Debug, Deserialize)]
+pub struct NewClipBody {
+    content: String,
+    title: Option<String>,
+    expires_at: field::ExpiresAt,
+    password: field::Password,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+impl TryFrom<NewClipBody> for service::ask::NewClip {
+    type Error = ClipError;
+
+    fn try_from(body: NewClipBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            content: field::Content::new(&body.content)?,
+            title: field::Title::new(body.title),
+            expires_at: body.expires_at,
+            password: body.password,
+            tags: field::Tags::new(body.tags)?,
+            language: field::Language::new(body.language)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateClipBody {
+    content: String,
+    title: Option<String>,
+    expires_at: field::ExpiresAt,
+    password: field::Password,
+    short_code: field::ShortCode,
+    #[serde(default)]
+    language: Option<String>,
+}
+
+impl TryFrom<UpdateClipBody> for service::ask::UpdateClip {
+    type Error = ClipError;
+
+    fn try_from(body: UpdateClipBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            content: field::Content::new(&body.content)?,
+            title: field::Title::new(body.title),
+            expires_at: body.expires_at,
+            password: body.password,
+            short_code: body.short_code,
+            language: field::Language::new(body.language)?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchClipBody {
+    #[serde(default)]
+    content: Patch<String>,
+    #[serde(default)]
+    title: Patch<String>,
+    #[serde(default)]
+    expires_at: Patch<field::ExpiresAt>,
+    #[serde(default)]
+    password: Patch<field::Password>,
+    #[serde(default)]
+    language: Patch<String>,
+}
+
+impl TryFrom<PatchClipBody> for service::ask::PatchClip {
+    type Error = ClipError;
+
+    fn try_from(body: PatchClipBody) -> Result<Self, Self::Error> {
+        Ok(Self {
+            content: body
+                .content
+                .try_map(|content| field::Content::new(&content))?,
+            title: body.title.map(field::Title::new),
+            expires_at: body.expires_at,
+            password: body.password,
+            language: body.language.try_map(field::Language::new)?,
+        })
+    }
+}
+
 #[rocket::post("/", data = "<req>")]
 pub async fn new_clip(
-    req: Json<service::ask::NewClip>,
+    req: Result<Json<NewClipBody>, json::Error<'_>>,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    metrics: &State<Metrics>,
     _api_key: ApiKey,
 ) -> Result<Json<Clip>, ApiError> {
-    let clip = action::new_clip(req.into_inner(), database.get_pool()).await?;
+    let req = service::ask::NewClip::try_from(req?.into_inner())?;
+    req.check(limits)?;
+
+    let clip = action::new_clip(req, store, database.get_pool()).await?;
+    metrics.count_created(1);
 
     Ok(Json(clip))
 }
 
-#[rocket::put("/", data = "<req>")]
-pub async fn update_clip(
-    req: Json<service::ask::UpdateClip>,
+#[rocket::post("/file", data = "<req>")]
+pub async fn new_clip_with_attachment(
+    req: Result<Form<form::NewClip<'_>>, Errors<'_>>,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    metrics: &State<Metrics>,
     _api_key: ApiKey,
 ) -> Result<Json<Clip>, ApiError> {
-    let clip = action::update_clip(req.into_inner(), database.get_pool()).await?;
+    let req = req?;
+
+    let clip = match req.into_inner().into_request(limits).await {
+        Ok((req, Some(attachment))) => {
+            action::new_clip_with_attachment(req, attachment, store, database.get_pool()).await?
+        }
+        Ok((req, None)) => action::new_clip(req, store, database.get_pool()).await?,
+        Err(UploadError::Clip(err)) => return Err(ServiceError::from(err).into()),
+        Err(UploadError::Io(err)) => {
+            eprintln!("failed to read upload: {}", err);
+            return Err(ApiError::server());
+        }
+    };
+    metrics.count_created(1);
 
     Ok(Json(clip))
 }
 
+#[rocket::post("/batch", data = "<req>")]
+pub async fn new_clips(
+    req: Result<Json<Vec<NewClipBody>>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    metrics: &State<Metrics>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<Clip>>, ApiError> {
+    let bodies = req?.into_inner();
+    check_batch_size(bodies.len())?;
+    let mut req = Vec::with_capacity(bodies.len());
+    for body in bodies {
+        let clip = service::ask::NewClip::try_from(body)?;
+        clip.check(limits)?;
+        req.push(clip);
+    }
+
+    let clips = action::new_clips(req, store, database.get_pool()).await?;
+    metrics.count_created(clips.len() as u64);
+
+    Ok(Json(clips))
+}
+
+#[rocket::post("/batch-get", data = "<req>")]
+pub async fn get_clips(
+    req: Result<Json<Vec<service::ask::GetClip>>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    hit_counter: &State<HitCounter>,
+    visitor: Visitor,
+    request_id: RequestId,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<BatchItem>>, ApiError> {
+    let req = req?.into_inner();
+    check_batch_size(req.len())?;
+
+    let short_codes: Vec<_> = req.iter().map(|req| req.short_code.clone()).collect();
+    let clips = action::get_clips(req, store, database.get_pool()).await;
+
+    let items = short_codes
+        .into_iter()
+        .zip(clips)
+        .map(|(short_code, clip)| match clip {
+            Ok(clip) => {
+                hit_counter.hit(short_code.clone(), visitor.clone());
+                BatchItem {
+                    short_code: short_code.into_inner(),
+                    clip: Some(clip),
+                    error: None,
+                }
+            }
+            Err(err) => BatchItem {
+                short_code: short_code.into_inner(),
+                clip: None,
+                error: Some(ApiError::from(err).into_body(request_id.clone())),
+            },
+        })
+        .collect();
+
+    Ok(Json(items))
+}
+
+#[rocket::put("/", data = "<req>")]
+pub async fn update_clip(
+    req: Result<Json<UpdateClipBody>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    events: &State<ClipEvents>,
+    limits: &State<ClipLimits>,
+    if_match: IfMatch,
+    _api_key: ApiKey,
+) -> Result<Tagged<Json<Clip>>, ApiError> {
+    let req = service::ask::UpdateClip::try_from(req?.into_inner())?;
+    req.check(limits)?;
+
+    let clip =
+        action::update_clip(req, if_match.versions(), events, store, database.get_pool()).await?;
+
+    Ok(Tagged::new(Json(clip.clone()), clip.version))
+}
+
+#[rocket::patch("/<short_code>", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn patch_clip(
+    short_code: &str,
+    req: Result<Json<PatchClipBody>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    events: &State<ClipEvents>,
+    limits: &State<ClipLimits>,
+    if_match: IfMatch,
+    _api_key: ApiKey,
+) -> Result<Tagged<Json<Clip>>, ApiError> {
+    let req = service::ask::PatchClip::try_from(req?.into_inner())?;
+    req.check(limits)?;
+
+    let clip = action::patch_clip(
+        short_code.into(),
+        req,
+        if_match.versions(),
+        events,
+        store,
+        database.get_pool(),
+    )
+    .await?;
+
+    Ok(Tagged::new(Json(clip.clone()), clip.version))
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![get_clip, new_clip, update_clip, new_api_key]
+    rocket::routes![
+        get_clip,
+        list_clips,
+        new_clip,
+        fork_clip,
+        get_clip_stats,
+        list_trash,
+        restore_clip,
+        new_clip_with_attachment,
+        new_clips,
+        get_clips,
+        update_clip,
+        patch_clip,
+        new_api_key
+    ]
+}
+
+#[rocket::post("/", data = "<req>")]
+pub async fn new_webhook(
+    req: Result<Json<service::ask::NewWebhook>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Webhook>, ApiError> {
+    let webhook = action::new_webhook(req?.into_inner(), database.get_pool()).await?;
+
+    Ok(Json(webhook))
+}
+
+#[rocket::get("/")]
+pub async fn list_webhooks(
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    Ok(Json(action::list_webhooks(database.get_pool()).await?))
+}
+
+#[rocket::delete("/<webhook_id>")]
+pub async fn delete_webhook(
+    webhook_id: &str,
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<status::NoContent, ApiError> {
+    action::delete_webhook(webhook_id, database.get_pool())
+        .await
+        .map_err(|err| match err {
+            ServiceError::NotFound => {
+                ApiError::new(Status::NotFound, "not_found", "webhook not found")
+            }
+            err => err.into(),
+        })?;
+
+    Ok(status::NoContent)
+}
+
+#[rocket::get("/dead-letters")]
+pub async fn list_dead_letters(
+    database: &State<AppDatabase>,
+    _api_key: ApiKey,
+) -> Result<Json<Vec<DeadLetter>>, ApiError> {
+    Ok(Json(action::list_dead_letters(database.get_pool()).await?))
+}
+
+pub fn webhook_routes() -> Vec<rocket::Route> {
+    rocket::routes![
+        new_webhook,
+        list_webhooks,
+        delete_webhook,
+        list_dead_letters
+    ]
+}
+
+/// Reports a missing collection as such, rather than as a missing clip.
+fn collection_error(err: ServiceError) -> ApiError {
+    match err {
+        ServiceError::NotFound => {
+            ApiError::new(Status::NotFound, "not_found", "collection not found")
+        }
+        err => err.into(),
+    }
+}
+
+#[rocket::post("/", data = "<req>")]
+pub async fn new_collection(
+    req: Result<Json<service::ask::NewCollection>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    _api_key: ApiKey,
+) -> Result<Json<Collection>, ApiError> {
+    let req = req?.into_inner();
+    limits.check_title(&req.title)?;
+
+    let collection = action::new_collection(req, store, database.get_pool()).await?;
+
+    Ok(Json(collection))
+}
+
+#[rocket::get("/<short_code>")]
+pub async fn get_collection(
+    short_code: &str,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    cookies: &CookieJar<'_>,
+    _api_key: ApiKey,
+) -> Result<Json<Collection>, ApiError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetCollection {
+        short_code: short_code.into(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .and_then(|cookie| Password::new(cookie.value().to_owned()).ok())
+            .unwrap_or_default(),
+    };
+    let collection = action::get_collection(req, store, database.get_pool())
+        .await
+        .map_err(collection_error)?;
+
+    Ok(Json(collection))
+}
+
+#[rocket::put("/<short_code>/clips", data = "<req>")]
+pub async fn set_collection_clips(
+    short_code: &str,
+    req: Result<Json<Vec<ShortCode>>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    cookies: &CookieJar<'_>,
+    _api_key: ApiKey,
+) -> Result<Json<Collection>, ApiError> {
+    use crate::domain::clip::field::Password;
+
+    let clips = req?.into_inner();
+    let req = service::ask::GetCollection {
+        short_code: short_code.into(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .and_then(|cookie| Password::new(cookie.value().to_owned()).ok())
+            .unwrap_or_default(),
+    };
+    let collection = action::set_collection_clips(req, clips, store, database.get_pool())
+        .await
+        .map_err(collection_error)?;
+
+    Ok(Json(collection))
+}
+
+pub fn collection_routes() -> Vec<rocket::Route> {
+    rocket::routes![new_collection, get_collection, set_collection_clips]
 }
 
 pub mod catcher {
-    use rocket::serde::json::Json;
+    use rocket::http::Status;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
+    use super::{ApiError, GuardError};
+
+    /// The error a request guard failed with, if that is why we got here.
+    fn guard_error(req: &Request) -> Option<ApiError> {
+        req.local_cache(|| GuardError(None)).0.clone()
+    }
+
     #[catch(default)]
-    fn default(req: &Request) -> Json<&'static str> {
+    fn default(status: Status, req: &Request) -> ApiError {
+        if let Some(err) = guard_error(req) {
+            return err;
+        }
+
         eprintln!("unhandled request: {}", req);
-        Json("something went wrong...")
+        let reason = status.reason().unwrap_or("unknown error");
+        let code = reason.to_lowercase().replace(' ', "_");
+        ApiError::new(status, code, reason.to_lowercase())
     }
 
     #[catch(500)]
-    fn internal_error(req: &Request) -> Json<&'static str> {
+    fn internal_error(req: &Request) -> ApiError {
         eprintln!("internal error: {}", req);
-        Json("internal server error")
+        ApiError::server()
     }
 
     #[catch(404)]
-    fn not_found() -> Json<&'static str> {
-        Json("404")
+    fn not_found() -> ApiError {
+        ApiError::new(Status::NotFound, "not_found", "not found")
     }
 
-    #[catch(401)]
-    fn request_error() -> Json<&'static str> {
-        Json("request error")
+    #[catch(400)]
+    fn bad_request(req: &Request) -> ApiError {
+        guard_error(req)
+            .unwrap_or_else(|| ApiError::new(Status::BadRequest, "bad_request", "bad request"))
     }
 
-    #[catch(400)]
-    fn invalid_api_key() -> Json<&'static str> {
-        Json("invalid api key")
+    #[catch(413)]
+    fn payload_too_large() -> ApiError {
+        ApiError::new(
+            Status::PayloadTooLarge,
+            "payload_too_large",
+            "request body too large",
+        )
     }
 
     pub fn catchers() -> Vec<Catcher> {
@@ -231,8 +917,408 @@ pub mod catcher {
             default,
             internal_error,
             not_found,
-            request_error,
-            invalid_api_key
+            bad_request,
+            payload_too_large
         ]
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use rocket::http::{uri::Origin, ContentType, Header, Method, Status};
+    use rocket::local::blocking::{Client, LocalRequest, LocalResponse};
+
+    use super::{ErrorBody, API_KEY_HEADER};
+    use crate::data::AppDatabase;
+    use crate::service::action;
+    use crate::service::events::{ClipChange, ClipEvents};
+    use crate::web::request_id::REQUEST_ID_HEADER;
+    use crate::web::test::init_test_client;
+
+    fn api_key(rt: &tokio::runtime::Runtime, client: &Client) -> Header<'static> {
+        let database = client.rocket().state::<AppDatabase>().unwrap();
+        let key = rt
+            .block_on(action::generate_api_key(database.get_pool()))
+            .expect("failed to generate api key");
+        Header::new(API_KEY_HEADER, key.to_base64())
+    }
+
+    /// A test client that sends an API key with every request.
+    struct ApiClient {
+        client: Client,
+        key: Header<'static>,
+        rt: tokio::runtime::Runtime,
+    }
+
+    impl ApiClient {
+        fn new() -> Self {
+            let (rt, client) = init_test_client();
+            let key = api_key(&rt, &client);
+            Self { client, key, rt }
+        }
+
+        fn req<'c, 'u: 'c, U>(&'c self, method: Method, uri: U) -> LocalRequest<'c>
+        where
+            U: TryInto<Origin<'u>> + std::fmt::Display,
+        {
+            self.client.req(method, uri).header(self.key.clone())
+        }
+
+        fn get<'c, 'u: 'c, U>(&'c self, uri: U) -> LocalResponse<'c>
+        where
+            U: TryInto<Origin<'u>> + std::fmt::Display,
+        {
+            self.req(Method::Get, uri).dispatch()
+        }
+
+        fn send_json<'c, 'u: 'c, U>(
+            &'c self,
+            method: Method,
+            uri: U,
+            body: serde_json::Value,
+        ) -> LocalResponse<'c>
+        where
+            U: TryInto<Origin<'u>> + std::fmt::Display,
+        {
+            self.req(method, uri)
+                .header(ContentType::JSON)
+                .body(body.to_string())
+                .dispatch()
+        }
+
+        /// Creates clips through the batch endpoint. Fields an item leaves out
+        /// are sent as null.
+        fn create_clips(&self, mut clips: serde_json::Value) -> Vec<crate::Clip> {
+            for clip in clips.as_array_mut().unwrap() {
+                for field in ["title", "expires_at", "password"] {
+                    clip.as_object_mut()
+                        .unwrap()
+                        .entry(field)
+                        .or_insert(serde_json::Value::Null);
+                }
+            }
+
+            let response = self.send_json(Method::Post, "/api/clip/batch", clips);
+            assert_eq!(response.status(), Status::Ok);
+            response.into_json().unwrap()
+        }
+    }
+
+    /// Asserts that `response` failed with `status` and the error `code`.
+    fn assert_error(response: LocalResponse<'_>, status: Status, code: &str) -> ErrorBody {
+        assert_eq!(response.status(), status);
+        let err: ErrorBody = response.into_json().unwrap();
+        assert_eq!(err.code, code);
+        err
+    }
+
+    #[test]
+    fn errors_have_structured_bodies() {
+        let (_rt, client) = init_test_client();
+
+        let response = client
+            .get("/api/clip/missing")
+            .header(Header::new(REQUEST_ID_HEADER, "req-1"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("req-1"));
+
+        let err: ErrorBody = response.into_json().unwrap();
+        assert_eq!(err.code, "api_key_not_found");
+        assert_eq!(err.request_id, "req-1");
+    }
+
+    #[test]
+    fn rejects_invalid_and_stale_updates() {
+        let client = ApiClient::new();
+
+        let body = serde_json::json!({
+            "content": "", "title": null, "expires_at": null, "password": null
+        });
+        let response = client.send_json(Method::Post, "/api/clip", body);
+        let err = assert_error(response, Status::BadRequest, "empty_content");
+        assert_eq!(err.field.as_deref(), Some("content"));
+
+        let clip = &client.create_clips(serde_json::json!([{ "content": "v1" }]))[0];
+        let url = format!("/api/clip/{}", clip.short_code.as_str());
+
+        let patch = |body: &str| {
+            client
+                .req(Method::Patch, url.as_str())
+                .header(Header::new("If-Match", "\"1\""))
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+        };
+        let response = patch(r#"{"content": "v2"}"#);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+
+        let response = patch(r#"{"title": "late"}"#);
+        assert_error(response, Status::PreconditionFailed, "version_mismatch");
+    }
+
+    #[test]
+    fn creates_and_fetches_clips_in_batches() {
+        use super::BatchItem;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "one" },
+            { "content": "two", "password": "secret" },
+        ]));
+        assert_eq!(clips.len(), 2);
+
+        let body = serde_json::json!([
+            { "short_code": clips[0].short_code.as_str() },
+            { "short_code": clips[1].short_code.as_str() },
+            { "short_code": "missing" },
+        ]);
+        let items: Vec<BatchItem> = client
+            .send_json(Method::Post, "/api/clip/batch-get", body)
+            .into_json()
+            .unwrap();
+
+        assert_eq!(items[0].clip.as_ref().unwrap().content.as_str(), "one");
+        assert_eq!(items[1].error.as_ref().unwrap().code, "permission_denied");
+        assert_eq!(items[2].error.as_ref().unwrap().code, "not_found");
+    }
+
+    #[test]
+    fn reports_views_per_day() {
+        use crate::domain::clip::stats::ClipStats;
+        use crate::web::hit_counter::HitCounter;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "a" },
+            { "content": "b", "password": "pw" },
+        ]));
+        let (open, secret) = (clips[0].short_code.as_str(), clips[1].short_code.as_str());
+
+        for user_agent in ["curl/8.0", "curl/8.0", "Firefox"] {
+            let response = client
+                .req(Method::Get, format!("/api/clip/{}", open))
+                .header(Header::new("User-Agent", user_agent))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let hit_counter = client.client.rocket().state::<HitCounter>().unwrap();
+        client.rt.block_on(hit_counter.shutdown());
+
+        let stats: ClipStats = client
+            .get(format!("/api/clip/{}/stats?days=3", open))
+            .into_json()
+            .unwrap();
+        assert_eq!(stats.hits.into_inner(), 3);
+        let views: Vec<_> = stats
+            .days
+            .iter()
+            .map(|day| (day.views, day.unique_views))
+            .collect();
+        assert_eq!(views, [(0, 0), (0, 0), (3, 2)]);
+        assert_eq!(
+            stats.days.last().unwrap().day,
+            chrono::Utc::now().date_naive()
+        );
+
+        let stats: ClipStats = client
+            .get(format!("/api/clip/{}/stats", open))
+            .into_json()
+            .unwrap();
+        assert_eq!(stats.days.len(), 30);
+
+        let response = client.get(format!("/api/clip/{}/stats", secret));
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn restores_expired_clips_from_the_trash() {
+        use crate::domain::clip::TrashedClip;
+        use crate::web::PASSWORD_COOKIE;
+        use rocket::http::Cookie;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "a", "title": "open" },
+            { "content": "b", "title": "secret", "password": "pw" },
+        ]));
+        let (open, secret) = (clips[0].short_code.as_str(), clips[1].short_code.as_str());
+
+        let rocket = client.client.rocket();
+        let pool = rocket.state::<AppDatabase>().unwrap().get_pool();
+        let events = rocket.state::<ClipEvents>().unwrap();
+        client.rt.block_on(async {
+            sqlx::query("UPDATE clips SET expires_at = 1")
+                .execute(pool)
+                .await
+                .unwrap();
+            action::trash_expired(events, pool).await.unwrap();
+        });
+
+        let response = client.get(format!("/api/clip/{}", open));
+        assert_eq!(response.status(), Status::NotFound);
+
+        let trash: Vec<TrashedClip> = client.get("/api/clip/trash").into_json().unwrap();
+        assert_eq!(trash.len(), 2);
+        let trashed = trash.iter().find(|clip| clip.protected).unwrap();
+        assert!(trashed.title.clone().into_inner().is_none());
+        assert!(trashed.expires_at.clone().into_inner().is_none());
+        let trashed = trash.iter().find(|clip| !clip.protected).unwrap();
+        assert!(trashed.title.clone().into_inner().is_some());
+
+        let restore = |short_code: &str| {
+            client.req(Method::Post, format!("/api/clip/{}/restore", short_code))
+        };
+        let response = restore(secret).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = restore(secret)
+            .cookie(Cookie::new(PASSWORD_COOKIE, "pw"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let mut changes = events.subscribe();
+        let clip: crate::Clip = restore(open).dispatch().into_json().unwrap();
+        assert!(clip.expires_at.into_inner().is_none());
+        match changes.try_recv().unwrap() {
+            ClipChange::Updated(restored) => assert_eq!(restored.short_code.as_str(), open),
+            change => panic!("unexpected change: {:?}", change),
+        }
+        let response = client.get(format!("/api/clip/{}", open));
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = restore(open).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn lists_clips_by_tag() {
+        use crate::domain::clip::ClipSummary;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "a", "title": "deploy", "tags": ["Incident-7", "backend"] },
+            { "content": "b", "title": "secret", "password": "pw", "tags": ["incident-7"] },
+            { "content": "c" },
+        ]));
+        assert_eq!(
+            clips[0].tags.clone().into_inner(),
+            ["backend", "incident-7"]
+        );
+
+        let listed: Vec<ClipSummary> = client.get("/api/clip?tag=INCIDENT-7").into_json().unwrap();
+        let mut short_codes: Vec<_> = listed.iter().map(|clip| clip.short_code.as_str()).collect();
+        short_codes.sort();
+        let mut expected = vec![clips[0].short_code.as_str(), clips[1].short_code.as_str()];
+        expected.sort();
+        assert_eq!(short_codes, expected);
+
+        let protected = listed.iter().find(|clip| clip.protected).unwrap();
+        assert!(protected.title.clone().into_inner().is_none());
+
+        let response = client.get("/api/clip?tag=a,b");
+        assert_error(response, Status::BadRequest, "invalid_tag");
+    }
+
+    #[test]
+    fn bundles_clips_into_collections() {
+        use crate::domain::collection::Collection;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "one", "title": "first" },
+            { "content": "two", "title": "second", "password": "pw" },
+        ]));
+        let codes: Vec<_> = clips.iter().map(|clip| clip.short_code.as_str()).collect();
+
+        let body = serde_json::json!({ "title": "incident", "clips": [codes[1], codes[0]] });
+        let collection: Collection = client
+            .send_json(Method::Post, "/api/collection", body)
+            .into_json()
+            .unwrap();
+        let members: Vec<_> = collection
+            .clips
+            .iter()
+            .map(|clip| clip.short_code.as_str())
+            .collect();
+        assert_eq!(members, [codes[1], codes[0]]);
+        assert!(collection.clips[0].preview.is_none());
+        assert_eq!(collection.clips[1].preview.as_deref(), Some("one"));
+
+        let uri = format!("/api/collection/{}/clips", collection.short_code.as_str());
+        let collection: Collection = client
+            .send_json(Method::Put, uri.as_str(), serde_json::json!([codes[0]]))
+            .into_json()
+            .unwrap();
+        assert_eq!(collection.clips.len(), 1);
+
+        let response = client.send_json(Method::Put, uri.as_str(), serde_json::json!(["missing"]));
+        assert_error(response, Status::BadRequest, "unknown_clip");
+
+        let response = client.get("/api/collection/missing");
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn protected_collections_need_their_password_to_change() {
+        use crate::domain::collection::Collection;
+        use crate::web::PASSWORD_COOKIE;
+        use rocket::http::Cookie;
+
+        let client = ApiClient::new();
+
+        let clips = client.create_clips(serde_json::json!([
+            { "content": "one" },
+            { "content": "two" },
+        ]));
+        let body = serde_json::json!({
+            "title": "incident",
+            "password": "pw",
+            "clips": [clips[0].short_code.as_str()],
+        });
+        let collection: Collection = client
+            .send_json(Method::Post, "/api/collection", body)
+            .into_json()
+            .unwrap();
+
+        let uri = format!("/api/collection/{}/clips", collection.short_code.as_str());
+        let replacement = serde_json::json!([clips[1].short_code.as_str()]).to_string();
+        let replace = |password: Option<&'static str>| {
+            let req = client
+                .req(Method::Put, uri.as_str())
+                .header(ContentType::JSON)
+                .body(replacement.as_str());
+            match password {
+                Some(password) => req.cookie(Cookie::new(PASSWORD_COOKIE, password)),
+                None => req,
+            }
+            .dispatch()
+        };
+        assert_error(replace(None), Status::Unauthorized, "permission_denied");
+        assert_error(
+            replace(Some("wrong")),
+            Status::Unauthorized,
+            "permission_denied",
+        );
+
+        let response = client
+            .req(
+                Method::Get,
+                format!("/api/collection/{}", collection.short_code.as_str()),
+            )
+            .cookie(Cookie::new(PASSWORD_COOKIE, "pw"))
+            .dispatch();
+        let unchanged: Collection = response.into_json().unwrap();
+        assert_eq!(unchanged.clips[0].short_code, clips[0].short_code);
+
+        let response = replace(Some("pw"));
+        assert_eq!(response.status(), Status::Ok);
+        let collection: Collection = response.into_json().unwrap();
+        assert_eq!(collection.clips[0].short_code, clips[1].short_code);
+    }
+}
@@ -1,6 +1,11 @@
 use derive_more::Constructor;
 use serde::Serialize;
 
+use crate::domain::clip::stats::ClipStats;
+use crate::domain::collection::Collection;
+use crate::web::highlight::{self, Code};
+use crate::web::markdown;
+use crate::web::sparkline::Sparkline;
 use crate::{Clip, ShortCode};
 
 pub trait PageContext {
@@ -9,15 +14,9 @@ pub trait PageContext {
     fn parent(&self) -> &str;
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct Home {}
 
-impl Default for Home {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 impl PageContext for Home {
     fn title(&self) -> &str {
         "Stash Your Clipboard!"
@@ -32,9 +31,53 @@ impl PageContext for Home {
     }
 }
 
-#[derive(Debug, Serialize, Constructor)]
+#[derive(Debug, Serialize)]
 pub struct ViewClip {
     clip: Clip,
+    code: Code,
+    highlighted: bool,
+    markdown: Option<String>,
+    views: Option<Sparkline>,
+}
+
+impl ViewClip {
+    /// Markdown clips are shown rendered, with their source a click away.
+    pub fn new(clip: Clip) -> Self {
+        let markdown = clip
+            .language
+            .or(&clip.detected_language)
+            .is_markdown()
+            .then(|| markdown::render(clip.content.as_str()));
+        Self {
+            code: highlight::plain(clip.content.as_str()),
+            clip,
+            highlighted: false,
+            markdown,
+            views: None,
+        }
+    }
+
+    /// Shows the clip with syntax highlighting.
+    pub fn highlighted(clip: Clip) -> Self {
+        Self {
+            code: highlight::highlight(
+                clip.content.as_str(),
+                clip.language.or(&clip.detected_language),
+            ),
+            clip,
+            highlighted: true,
+            markdown: None,
+            views: None,
+        }
+    }
+
+    /// Draws the recent views of the clip, when they could be loaded.
+    pub fn with_views(self, stats: Option<&ClipStats>) -> Self {
+        Self {
+            views: stats.map(Sparkline::new),
+            ..self
+        }
+    }
 }
 
 impl PageContext for ViewClip {
@@ -52,8 +95,45 @@ impl PageContext for ViewClip {
 }
 
 #[derive(Debug, Serialize, Constructor)]
+pub struct ViewCollection {
+    collection: Collection,
+}
+
+impl PageContext for ViewCollection {
+    fn title(&self) -> &str {
+        "View Collection"
+    }
+
+    fn template_path(&self) -> &str {
+        "collection"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// Asks for the password of a clip, or of a collection.
+#[derive(Debug, Serialize)]
 pub struct PasswordRequired {
     short_code: ShortCode,
+    kind: &'static str,
+}
+
+impl PasswordRequired {
+    pub fn new(short_code: ShortCode) -> Self {
+        Self {
+            short_code,
+            kind: "clip",
+        }
+    }
+
+    pub fn for_collection(short_code: ShortCode) -> Self {
+        Self {
+            short_code,
+            kind: "collection",
+        }
+    }
 }
 
 impl PageContext for PasswordRequired {
@@ -0,0 +1,152 @@
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::convert::Infallible;
+
+use crate::domain::clip::field::Version;
+
+/// Strong entity tag of a clip, derived from its version.
+#[derive(Debug, Clone, Copy)]
+pub struct ETag(Version);
+
+impl From<Version> for ETag {
+    fn from(version: Version) -> Self {
+        Self(version)
+    }
+}
+
+impl From<ETag> for Header<'static> {
+    fn from(etag: ETag) -> Self {
+        Header::new("ETag", format!("\"{}\"", etag.0))
+    }
+}
+
+/// Parsed value of an `If-Match` or `If-None-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityTags {
+    Any,
+    Versions(Vec<Version>),
+}
+
+impl EntityTags {
+    /// Tags that are not versions of a clip are dropped; they can never match.
+    /// `If-Match` uses strong comparison, so weak tags are only kept when `weak` is set.
+    fn parse(value: &str, weak: bool) -> Self {
+        if value.trim() == "*" {
+            return Self::Any;
+        }
+
+        let versions = value
+            .split(',')
+            .filter_map(|tag| {
+                let tag = tag.trim();
+                let tag = match tag.strip_prefix("W/") {
+                    Some(tag) if weak => tag,
+                    Some(_) => return None,
+                    None => tag,
+                };
+                tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok()
+            })
+            .map(Version::new)
+            .collect();
+
+        Self::Versions(versions)
+    }
+
+    pub fn matches(&self, version: Version) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Versions(versions) => versions.contains(&version),
+        }
+    }
+}
+
+pub struct IfMatch(Option<EntityTags>);
+
+impl IfMatch {
+    /// Versions a write may replace, or `None` if any version will do.
+    pub fn versions(&self) -> Option<&[Version]> {
+        match &self.0 {
+            Some(EntityTags::Versions(versions)) => Some(versions),
+            _ => None,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tags = req
+            .headers()
+            .get_one("If-Match")
+            .map(|value| EntityTags::parse(value, false));
+        Outcome::Success(Self(tags))
+    }
+}
+
+pub struct IfNoneMatch(Option<EntityTags>);
+
+impl IfNoneMatch {
+    pub fn matches(&self, version: Version) -> bool {
+        self.0.as_ref().is_some_and(|tags| tags.matches(version))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let tags = req
+            .headers()
+            .get_one("If-None-Match")
+            .map(|value| EntityTags::parse(value, true));
+        Outcome::Success(Self(tags))
+    }
+}
+
+/// A response carrying an `ETag`, or an empty `304` when the client's copy is current.
+#[derive(rocket::Responder)]
+pub enum Tagged<R> {
+    Fresh(R, ETag),
+    #[response(status = 304)]
+    NotModified((), ETag),
+}
+
+impl<R> Tagged<R> {
+    pub fn new(inner: R, version: Version) -> Self {
+        Self::Fresh(inner, version.into())
+    }
+
+    /// Skips building the body entirely when `if_none_match` already has this version.
+    pub fn or_not_modified<F>(version: Version, if_none_match: &IfNoneMatch, inner: F) -> Self
+    where
+        F: FnOnce() -> R,
+    {
+        if if_none_match.matches(version) {
+            Self::NotModified((), version.into())
+        } else {
+            Self::new(inner(), version)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parses_entity_tags() {
+        let versions =
+            |vs: &[u64]| EntityTags::Versions(vs.iter().copied().map(Version::new).collect());
+
+        assert_eq!(EntityTags::parse("*", false), EntityTags::Any);
+        assert_eq!(EntityTags::parse("\"3\"", false), versions(&[3]));
+        assert_eq!(
+            EntityTags::parse("\"1\", W/\"2\", \"x\"", false),
+            versions(&[1])
+        );
+        assert_eq!(EntityTags::parse("\"1\", W/\"2\"", true), versions(&[1, 2]));
+    }
+}
@@ -1,14 +1,122 @@
+use rocket::fs::TempFile;
+use rocket::tokio::io::AsyncReadExt;
 use rocket::FromForm;
 use serde::Serialize;
 
-use crate::domain::clip::field;
+use crate::domain::clip::{field, limits::ClipLimits};
+use crate::service::ask;
+use crate::ClipError;
 
-#[derive(Debug, Serialize, FromForm)]
-pub struct NewClip {
-    pub content: field::Content,
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("{0}")]
+    Clip(#[from] ClipError),
+    #[error("failed to read upload: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, FromForm)]
+pub struct NewClip<'r> {
+    pub content: String,
     pub title: field::Title,
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
+    pub tags: field::Tags,
+    pub language: field::Language,
+    pub attachment: Option<TempFile<'r>>,
+}
+
+impl NewClip<'_> {
+    /// Builds the service request, falling back to the file name as content
+    /// when only a file was uploaded.
+    pub async fn into_request(
+        self,
+        limits: &ClipLimits,
+    ) -> Result<(ask::NewClip, Option<ask::NewAttachment>), UploadError> {
+        let attachment = match self.attachment {
+            Some(file) if file.len() > 0 => Some(Self::read_attachment(&file).await?),
+            _ => None,
+        };
+
+        let content = match &attachment {
+            Some(attachment) if self.content.trim().is_empty() => attachment.default_content()?,
+            _ => field::Content::new(&self.content)?,
+        };
+
+        let req = ask::NewClip {
+            content,
+            title: self.title,
+            expires_at: self.expires_at,
+            password: self.password,
+            tags: self.tags,
+            language: self.language,
+        };
+
+        req.check(limits)?;
+        if let Some(attachment) = &attachment {
+            attachment.check(limits)?;
+        }
+
+        Ok((req, attachment))
+    }
+
+    async fn read_attachment(file: &TempFile<'_>) -> Result<ask::NewAttachment, UploadError> {
+        let mut content = Vec::new();
+        file.open().await?.read_to_end(&mut content).await?;
+
+        let file_name = file
+            .raw_name()
+            .map(|name| name.dangerous_unsafe_unsanitized_raw().as_str())
+            .unwrap_or_default();
+        let content_type = file
+            .content_type()
+            .map(|content_type| content_type.to_string())
+            .unwrap_or_default();
+
+        Ok(ask::NewAttachment::new(file_name, &content_type, content)?)
+    }
+}
+
+/// Query parameters of a raw paste. They are validated along with the body.
+#[derive(Debug, FromForm)]
+pub struct PasteOptions {
+    pub title: Option<String>,
+    pub expires_at: Option<String>,
+    pub password: Option<String>,
+    pub tags: Option<String>,
+    pub language: Option<String>,
+}
+
+/// A 1-based, inclusive range of lines, written as `10` or `10-25`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    /// The lines of `content` in this range, with their line endings.
+    pub fn select(&self, content: &str) -> String {
+        content
+            .split_inclusive('\n')
+            .skip(self.start - 1)
+            .take(self.end - self.start + 1)
+            .collect()
+    }
+}
+
+impl std::str::FromStr for LineRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').unwrap_or((s, s));
+        let start: usize = start.trim().parse().map_err(|_| ())?;
+        let end: usize = end.trim().parse().map_err(|_| ())?;
+        if start == 0 || end < start {
+            return Err(());
+        }
+        Ok(Self { start, end })
+    }
 }
 
 #[derive(Debug, Serialize, FromForm)]
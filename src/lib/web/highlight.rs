@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
+use syntect::util::LinesWithEndings;
+
+use crate::domain::clip::{field::Language, syntax};
+
+const THEME: &str = "InspiredGitHub";
+
+fn theme() -> &'static Theme {
+    static THEME_SET: OnceLock<Theme> = OnceLock::new();
+    THEME_SET.get_or_init(|| {
+        ThemeSet::load_defaults()
+            .themes
+            .remove(THEME)
+            .expect("default themes should include the highlighting theme")
+    })
+}
+
+/// A line of clip content as HTML, numbered from 1.
+#[derive(Debug, Serialize)]
+pub struct Line {
+    pub number: usize,
+    pub html: String,
+}
+
+/// Clip content rendered as HTML, one line at a time.
+#[derive(Debug, Serialize)]
+pub struct Code {
+    pub background: String,
+    pub lines: Vec<Line>,
+}
+
+impl Code {
+    fn new(lines: Vec<String>) -> Self {
+        let background = theme()
+            .settings
+            .background
+            .map(|c| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b))
+            .unwrap_or_else(|| "#ffffff".to_owned());
+        let lines = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, html)| Line {
+                number: i + 1,
+                html,
+            })
+            .collect();
+
+        Self { background, lines }
+    }
+}
+
+/// Escapes `content` without highlighting it.
+pub fn plain(content: &str) -> Code {
+    Code::new(content.lines().map(handlebars::html_escape).collect())
+}
+
+/// Highlights `content` as `language`, or leaves it plain when no language is
+/// known or highlighting fails.
+pub fn highlight(content: &str, language: &Language) -> Code {
+    let lines = language
+        .as_deref()
+        .and_then(syntax::find)
+        .and_then(|syntax| {
+            let set = syntax::syntax_set();
+            let mut highlighter = HighlightLines::new(syntax, theme());
+
+            LinesWithEndings::from(content)
+                .map(|line| {
+                    let ranges = highlighter.highlight_line(line, set).ok()?;
+                    let ranges: Vec<_> = ranges
+                        .into_iter()
+                        .map(|(style, text)| (style, text.trim_end_matches(['\r', '\n'])))
+                        .collect();
+                    styled_line_to_highlighted_html(&ranges, IncludeBackground::No).ok()
+                })
+                .collect::<Option<Vec<_>>>()
+        });
+
+    match lines {
+        Some(lines) => Code::new(lines),
+        None => plain(content),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn highlights_known_languages() {
+        let rust = Language::new("rust".to_owned()).unwrap();
+        let code = highlight("fn main() {}\nlet x = \"<b>\";\n", &rust);
+        assert_eq!(code.lines.len(), 2);
+        assert_eq!(code.lines[1].number, 2);
+        assert!(code.lines[0].html.contains("<span"));
+        assert!(code.lines[1].html.contains("&lt;b&gt;"));
+
+        let code = highlight("<b>\n", &Language::default());
+        assert_eq!(code.lines[0].html, "&lt;b&gt;");
+    }
+}
@@ -1,118 +1,380 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::{unbounded, Sender};
-use parking_lot::Mutex;
+use chrono::NaiveDate;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::tokio::sync::{mpsc, oneshot};
+use rocket::{Orbit, Rocket};
+use serde::Serialize;
 use tokio::runtime::Handle;
 
-use crate::{
-    data::DatabasePool,
-    service::{self, ServiceError},
-    ShortCode,
-};
-
-type HitStore = Arc<Mutex<HashMap<ShortCode, u32>>>;
-type Result<T> = std::result::Result<T, HitCountError>;
-
-#[derive(Debug, thiserror::Error)]
-enum HitCountError {
-    #[error("service error: {0}")]
-    Service(#[from] ServiceError),
-    #[error("communication error: {0}")]
-    Channel(#[from] crossbeam_channel::SendError<HitCountMsg>),
+use super::visitor::Visitor;
+use crate::{data::DatabasePool, service, ShortCode};
+
+#[derive(Clone, Debug)]
+pub struct HitCounterConfig {
+    /// Hits waiting to be counted before new ones are dropped.
+    pub capacity: usize,
+    /// How often pending hits are committed.
+    pub flush_interval: Duration,
+    /// Pending clips that trigger a commit before the interval is up.
+    pub flush_size: usize,
+    /// Failed commits a hit survives before it is given up on.
+    pub max_attempts: u32,
+}
+
+impl Default for HitCounterConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 4096,
+            flush_interval: Duration::from_secs(5),
+            flush_size: 256,
+            max_attempts: 5,
+        }
+    }
+}
+
+/// Hits waiting to be committed, per clip and day.
+type Pending = HashMap<(ShortCode, NaiveDate), PendingViews>;
+
+#[derive(Default)]
+struct PendingViews {
+    count: u32,
+    visitors: HashSet<String>,
+    /// Failed commits these views were part of.
+    failures: u32,
 }
 
 enum HitCountMsg {
-    Commit,
-    Hit(ShortCode, u32),
+    Hit(ShortCode, Visitor),
+    /// Commits pending hits, stops the task and then reports back.
+    Shutdown(oneshot::Sender<()>),
 }
 
+#[derive(Debug, Default)]
+struct Metrics {
+    received: AtomicU64,
+    dropped: AtomicU64,
+    committed: AtomicU64,
+    flushes: AtomicU64,
+    failed_flushes: AtomicU64,
+    abandoned: AtomicU64,
+    flush_micros: AtomicU64,
+}
+
+/// How well the hit counter keeps up. Hits are dropped rather than slowing
+/// down requests when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HitCounterStats {
+    pub received: u64,
+    pub dropped: u64,
+    pub committed: u64,
+    pub flushes: u64,
+    pub failed_flushes: u64,
+    /// Hits given up on after `max_attempts` failed commits.
+    pub abandoned: u64,
+    /// Time spent committing hits, failed flushes included.
+    pub flush_time: Duration,
+    pub queued: u64,
+}
+
+/// Counts clip views in memory and commits them in batches from a tokio task.
 pub struct HitCounter {
-    tx: Sender<HitCountMsg>,
+    tx: mpsc::Sender<HitCountMsg>,
+    metrics: Arc<Metrics>,
 }
 
 impl HitCounter {
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
-        let (tx, rx) = unbounded();
-        let tx_clone = tx.clone();
-        let rx_clone = rx.clone();
-
-        let _ = std::thread::spawn(move || {
-            println!("HitCounter thread spawned");
-
-            let store: HitStore = Arc::new(Mutex::new(HashMap::new()));
-
-            loop {
-                match rx_clone.try_recv() {
-                    Ok(msg) => {
-                        if let Err(err) =
-                            Self::process_msg(msg, store.clone(), handle.clone(), pool.clone())
-                        {
-                            eprintln!("failed to process message: {}", err);
+    pub fn new(pool: DatabasePool, handle: Handle, config: HitCounterConfig) -> Self {
+        let (tx, rx) = mpsc::channel(config.capacity.max(1));
+        let metrics = Arc::new(Metrics::default());
+
+        handle.spawn(Self::run(rx, pool, config, Arc::clone(&metrics)));
+
+        Self { tx, metrics }
+    }
+
+    async fn run(
+        mut rx: mpsc::Receiver<HitCountMsg>,
+        pool: DatabasePool,
+        config: HitCounterConfig,
+        metrics: Arc<Metrics>,
+    ) {
+        let mut pending = Pending::new();
+        // A zero period would make `interval` panic.
+        let mut interval =
+            tokio::time::interval(config.flush_interval.max(Duration::from_millis(1)));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            rocket::tokio::select! {
+                msg = rx.recv() => match msg {
+                    Some(HitCountMsg::Hit(short_code, visitor)) => {
+                        let views = pending.entry((short_code, visitor.day())).or_default();
+                        views.count += 1;
+                        views.visitors.insert(visitor.into_inner());
+                        if pending.len() >= config.flush_size {
+                            Self::flush(&mut pending, &pool, &config, &metrics).await;
                         }
                     }
-                    Err(err) => match err {
-                        crossbeam_channel::TryRecvError::Empty => {
-                            std::thread::sleep(std::time::Duration::from_secs(5));
+                    Some(HitCountMsg::Shutdown(done)) => {
+                        // Refuse new hits before reporting back, not once the task ends.
+                        rx.close();
+                        Self::flush(&mut pending, &pool, &config, &metrics).await;
+                        let _ = done.send(());
+                        break;
+                    }
+                    None => {
+                        Self::flush(&mut pending, &pool, &config, &metrics).await;
+                        break;
+                    }
+                },
+                _ = interval.tick() => Self::flush(&mut pending, &pool, &config, &metrics).await,
+            }
+        }
+    }
 
-                            if let Err(err) = tx_clone.send(HitCountMsg::Commit) {
-                                eprintln!("failed to send commit message: {}", err);
-                            }
-                        }
-                        _ => break,
-                    },
+    /// Commits pending hits. A failed batch stays pending for the next flush,
+    /// until it has failed `max_attempts` times.
+    async fn flush(
+        pending: &mut Pending,
+        pool: &DatabasePool,
+        config: &HitCounterConfig,
+        metrics: &Metrics,
+    ) {
+        if pending.is_empty() {
+            return;
+        }
+
+        let batch: Vec<_> = pending.drain().collect();
+        let views: Vec<service::ask::NewViews> = batch
+            .iter()
+            .map(|((short_code, day), views)| service::ask::NewViews {
+                short_code: short_code.clone(),
+                day: *day,
+                count: views.count,
+                visitors: views.visitors.iter().cloned().collect(),
+            })
+            .collect();
+        let committed: u64 = views.iter().map(|views| u64::from(views.count)).sum();
+        let started = Instant::now();
+        let result = service::action::increase_hit_counts(views, pool).await;
+        let micros = u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX);
+        metrics.flush_micros.fetch_add(micros, Ordering::Relaxed);
+        match result {
+            Ok(()) => {
+                metrics.committed.fetch_add(committed, Ordering::Relaxed);
+                metrics.flushes.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => {
+                eprintln!("failed to commit hits: {}", err);
+                metrics.failed_flushes.fetch_add(1, Ordering::Relaxed);
+                for (key, mut failed) in batch {
+                    failed.failures += 1;
+                    if failed.failures >= config.max_attempts {
+                        let count = u64::from(failed.count);
+                        metrics.abandoned.fetch_add(count, Ordering::Relaxed);
+                    } else {
+                        pending.insert(key, failed);
+                    }
                 }
             }
-        });
-
-        Self { tx }
+        }
     }
 
-    fn process_msg(
-        msg: HitCountMsg,
-        hits: HitStore,
-        handle: Handle,
-        pool: DatabasePool,
-    ) -> Result<()> {
-        match msg {
-            HitCountMsg::Commit => Self::commit_hits(hits.clone(), handle.clone(), pool.clone())?,
-            HitCountMsg::Hit(short_code, count) => {
-                let mut hit_count = hits.lock();
-                let hit_count = hit_count.entry(short_code).or_insert(0);
-                *hit_count += count;
+    /// Counts a view towards the day the visitor was seen on.
+    pub fn hit(&self, short_code: ShortCode, visitor: Visitor) {
+        self.metrics.received.fetch_add(1, Ordering::Relaxed);
+        if let Err(err) = self.tx.try_send(HitCountMsg::Hit(short_code, visitor)) {
+            self.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+            if let mpsc::error::TrySendError::Closed(_) = err {
+                eprintln!("hit counter is stopped, dropping hits");
             }
         }
+    }
 
-        Ok(())
+    pub fn stats(&self) -> HitCounterStats {
+        HitCounterStats {
+            received: self.metrics.received.load(Ordering::Relaxed),
+            dropped: self.metrics.dropped.load(Ordering::Relaxed),
+            committed: self.metrics.committed.load(Ordering::Relaxed),
+            flushes: self.metrics.flushes.load(Ordering::Relaxed),
+            failed_flushes: self.metrics.failed_flushes.load(Ordering::Relaxed),
+            abandoned: self.metrics.abandoned.load(Ordering::Relaxed),
+            flush_time: Duration::from_micros(self.metrics.flush_micros.load(Ordering::Relaxed)),
+            queued: (self.tx.max_capacity() - self.tx.capacity()) as u64,
+        }
     }
 
-    fn commit_hits(hits: HitStore, handle: Handle, pool: DatabasePool) -> Result<()> {
-        let hits = Arc::clone(&hits);
+    /// Commits every pending hit and stops the task. Hits sent afterwards
+    /// are dropped.
+    pub async fn shutdown(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(HitCountMsg::Shutdown(done_tx)).await.is_err() {
+            return;
+        }
+        let _ = done_rx.await;
+    }
+}
 
-        let hits: Vec<(ShortCode, u32)> = {
-            let mut hits = hits.lock();
-            let hits_vec = hits.iter().map(|(k, v)| (k.clone(), *v)).collect();
-            hits.clear();
-            hits_vec
-        };
+/// Flushes the hit counter when Rocket shuts down.
+pub struct HitCounterFairing;
 
-        handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
+#[rocket::async_trait]
+impl Fairing for HitCounterFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Hit counter flush",
+            kind: Kind::Shutdown,
+        }
+    }
 
-            for (short_code, count) in hits {
-                if let Err(err) =
-                    service::action::increase_hit_count(&short_code, count, &pool).await
-                {
-                    eprintln!("failed to increase hit count: {}", err);
-                }
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        if let Some(hit_counter) = rocket.state::<HitCounter>() {
+            hit_counter.shutdown().await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{HitCounter, HitCounterConfig, HitCounterStats, Visitor};
+    use crate::data::AppDatabase;
+    use crate::test::async_runtime;
+    use crate::web::test::init_test_client;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
+    use std::time::Duration;
+
+    fn paste(client: &Client, content: &str) -> String {
+        let response = client
+            .post("/")
+            .header(ContentType::Plain)
+            .body(content)
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let url = response.into_string().unwrap();
+        url.trim().rsplit('/').next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn commits_full_batches_early() {
+        let rt = async_runtime();
+        let mut config = crate::web::test::config(rt.handle());
+        config.hit_counter = HitCounter::new(
+            config.database.get_pool().clone(),
+            rt.handle().clone(),
+            HitCounterConfig {
+                flush_interval: Duration::from_secs(60 * 60),
+                flush_size: 2,
+                ..Default::default()
+            },
+        );
+        let client = crate::web::test::client(config);
+
+        let (a, b) = (paste(&client, "a"), paste(&client, "b"));
+        for short_code in [&a, &a, &b] {
+            let response = client.get(format!("/{}", short_code)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let hit_counter = client.rocket().state::<HitCounter>().unwrap();
+        for _ in 0..100 {
+            if hit_counter.stats().flushes > 0 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        let stats = hit_counter.stats();
+        assert_eq!(
+            stats,
+            HitCounterStats {
+                received: 3,
+                committed: 3,
+                flushes: 1,
+                flush_time: stats.flush_time,
+                ..Default::default()
             }
+        );
 
-            Ok(service::action::end_transaction(transaction).await?)
-        })
+        let pool = client.rocket().state::<AppDatabase>().unwrap().get_pool();
+        let hits: Vec<i64> = rt
+            .block_on(
+                sqlx::query_scalar(
+                    "SELECT hits FROM clips WHERE short_code IN (?, ?) ORDER BY hits",
+                )
+                .bind(&a)
+                .bind(&b)
+                .fetch_all(pool),
+            )
+            .unwrap();
+        assert_eq!(hits, [1, 2]);
+
+        rt.block_on(hit_counter.shutdown());
+        let key = Default::default();
+        let visitor = Visitor::new(&key, chrono::Utc::now().date_naive(), None, None);
+        hit_counter.hit(a.into(), visitor);
+        assert_eq!(hit_counter.stats().dropped, 1);
     }
 
-    pub fn hit(&self, short_code: ShortCode, count: u32) {
-        if let Err(err) = self.tx.send(HitCountMsg::Hit(short_code, count)) {
-            eprintln!("failed to send hit count: {}", err);
+    #[test]
+    fn gives_up_on_hits_that_keep_failing() {
+        let rt = async_runtime();
+        let db = crate::data::test::new_db(rt.handle());
+        let pool = db.get_pool().clone();
+        rt.block_on(pool.close());
+
+        let hit_counter = HitCounter::new(
+            pool,
+            rt.handle().clone(),
+            HitCounterConfig {
+                flush_interval: Duration::from_millis(10),
+                max_attempts: 3,
+                ..Default::default()
+            },
+        );
+        let (key, day) = (Default::default(), chrono::Utc::now().date_naive());
+        for short_code in ["a", "a", "b"] {
+            hit_counter.hit(short_code.into(), Visitor::new(&key, day, None, None));
         }
+
+        for _ in 0..100 {
+            if hit_counter.stats().abandoned == 3 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let stats = hit_counter.stats();
+        assert_eq!(stats.abandoned, 3);
+        assert!(stats.failed_flushes >= 3);
+        assert_eq!(stats.committed, 0);
+
+        // nothing is left to retry
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(hit_counter.stats().failed_flushes, stats.failed_flushes);
+    }
+
+    #[test]
+    fn hits_survive_shutdown() {
+        let (rt, client) = init_test_client();
+        let short_code = paste(&client, "counted");
+
+        for _ in 0..3 {
+            let response = client.get(format!("/{}", short_code)).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let rocket = client.terminate();
+        let pool = rocket.state::<AppDatabase>().unwrap().get_pool();
+        let hits: i64 = rt
+            .block_on(
+                sqlx::query_scalar("SELECT hits FROM clips WHERE short_code = ?")
+                    .bind(&short_code)
+                    .fetch_one(pool),
+            )
+            .unwrap();
+        assert_eq!(hits, 3);
     }
 }
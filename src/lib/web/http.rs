@@ -1,16 +1,27 @@
+use rocket::data::{ByteUnit, Data};
 use rocket::form::{Contextual, Form};
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{ContentType, Cookie, CookieJar, Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
 use rocket::response::content::RawHtml;
+use rocket::response::stream::{Event, EventStream};
 use rocket::response::{status, Redirect};
-use rocket::{uri, State};
+use rocket::{uri, Shutdown, State};
 
+use crate::data::content::ContentStore;
 use crate::data::AppDatabase;
+use crate::domain::clip::field::Attachment;
+use crate::domain::clip::limits::ClipLimits;
+use crate::domain::clip::stats::{self, ClipStats};
+use crate::service::events::{ClipChange, ClipEvents};
 use crate::service::{self, action, ServiceError};
 use crate::web::hit_counter::HitCounter;
-use crate::ShortCode;
+use crate::web::metrics::Metrics;
+use crate::web::visitor::Visitor;
+use crate::{Clip, ClipError, ShortCode, Time};
 
+use super::etag::{IfNoneMatch, Tagged};
 use super::renderer::Renderer;
-use super::{ctx, form, PageError, PASSWORD_COOKIE};
+use super::{ctx, form, PageError, PublicUrl, PASSWORD_COOKIE};
 
 #[rocket::get("/")]
 fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
@@ -18,60 +29,111 @@ fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
     RawHtml(renderer.render(context, &[]))
 }
 
+/// Views of a clip over the last days, for its sparkline. A clip page is
+/// still shown when they cannot be loaded.
+async fn recent_views(clip: &Clip, database: &AppDatabase) -> Option<ClipStats> {
+    match action::clip_stats(clip, stats::DEFAULT_DAYS, database.get_pool()).await {
+        Ok(stats) => Some(stats),
+        Err(err) => {
+            eprintln!("failed to load views of clip: {}", err);
+            None
+        }
+    }
+}
+
+/// Renders a clip page with `view`, or asks for the clip's password.
+async fn show_clip(
+    req: service::ask::GetClip,
+    view: fn(Clip) -> ctx::ViewClip,
+    database: &AppDatabase,
+    store: &ContentStore,
+    hit_counter: &HitCounter,
+    visitor: Visitor,
+    renderer: &Renderer<'_>,
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    let short_code = req.short_code.clone();
+
+    match action::get_clip(req, store, database.get_pool()).await {
+        Ok(clip) => {
+            hit_counter.hit(short_code, visitor);
+            let views = recent_views(&clip, database).await;
+            let context = view(clip).with_views(views.as_ref());
+            Ok(status::Custom(
+                Status::Ok,
+                RawHtml(renderer.render(context, &[])),
+            ))
+        }
+        Err(ServiceError::PermissionError(_)) => {
+            let context = ctx::PasswordRequired::new(short_code);
+            Ok(status::Custom(
+                Status::Unauthorized,
+                RawHtml(renderer.render(context, &[])),
+            ))
+        }
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("clip not found".to_owned())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
 #[rocket::get("/<short_code>")]
 async fn get_clip(
     short_code: ShortCode,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
     hit_counter: &State<HitCounter>,
+    visitor: Visitor,
     renderer: &State<Renderer<'_>>,
 ) -> Result<status::Custom<RawHtml<String>>, PageError> {
-    fn render_with_status<T: ctx::PageContext + serde::Serialize + std::fmt::Debug>(
-        status: Status,
-        context: T,
-        renderer: &Renderer,
-    ) -> Result<status::Custom<RawHtml<String>>, PageError> {
-        Ok(status::Custom(
-            status,
-            RawHtml(renderer.render(context, &[])),
-        ))
-    }
-
-    match action::get_clip(short_code.clone().into(), database.get_pool()).await {
-        Ok(clip) => {
-            hit_counter.hit(short_code.clone(), 1);
-            let context = ctx::ViewClip::new(clip);
-
-            render_with_status(Status::Ok, context, renderer)
-        }
-        Err(err) => match err {
-            ServiceError::PermissionError(_) => {
-                let context = ctx::PasswordRequired::new(short_code);
-                render_with_status(Status::Unauthorized, context, renderer)
-            }
-            ServiceError::NotFound => Err(PageError::NotFound("clip not found".to_owned())),
-            _ => Err(PageError::Internal("server error".to_owned())),
-        },
-    }
+    show_clip(
+        short_code.into(),
+        ctx::ViewClip::new,
+        database,
+        store,
+        hit_counter,
+        visitor,
+        renderer,
+    )
+    .await
 }
 
-#[rocket::post("/", data = "<form>")]
+#[rocket::post("/", data = "<form>", rank = 2)]
 async fn new_clip(
-    form: Form<Contextual<'_, form::NewClip>>,
+    form: Form<Contextual<'_, form::NewClip<'_>>>,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    metrics: &State<Metrics>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<Redirect, (Status, RawHtml<String>)> {
     let form = form.into_inner();
 
     if let Some(value) = form.value {
-        let req = service::ask::NewClip {
-            content: value.content,
-            title: value.title,
-            expires_at: value.expires_at,
-            password: value.password,
+        let (req, attachment) = match value.into_request(limits).await {
+            Ok(req) => req,
+            Err(err) => {
+                return Err((
+                    Status::BadRequest,
+                    RawHtml(renderer.render_with_data(
+                        ctx::Home::default(),
+                        ("clip", &form.context),
+                        &[err.to_string().as_str()],
+                    )),
+                ))
+            }
+        };
+
+        let clip = match attachment {
+            Some(attachment) => {
+                action::new_clip_with_attachment(req, attachment, store, database.get_pool()).await
+            }
+            None => action::new_clip(req, store, database.get_pool()).await,
         };
 
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(short_code = clip.short_code)))),
+        match clip {
+            Ok(clip) => {
+                metrics.count_created(1);
+                Ok(Redirect::to(uri!(get_clip(short_code = clip.short_code))))
+            }
             Err(err) => {
                 eprintln!("internal error: {}", err);
                 Err((
@@ -109,13 +171,112 @@ async fn new_clip(
     }
 }
 
+/// A client posting a raw body to `/`, as opposed to a browser submitting the
+/// home page form.
+struct PasteClient {
+    origin: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PasteClient {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let is_form = req
+            .content_type()
+            .is_some_and(|ty| ty.is_form() || ty.is_form_data());
+        let wants_html = req
+            .headers()
+            .get("Accept")
+            .any(|accept| accept.contains("text/html"));
+
+        if is_form || wants_html {
+            return Outcome::Forward(Status::NotFound);
+        }
+
+        let origin = req
+            .rocket()
+            .state::<PublicUrl>()
+            .cloned()
+            .unwrap_or_default()
+            .origin(req.rocket().config());
+
+        Outcome::Success(Self { origin })
+    }
+}
+
+#[rocket::post("/?<options..>", data = "<content>", rank = 1)]
+async fn paste_clip(
+    client: PasteClient,
+    options: form::PasteOptions,
+    content: Data<'_>,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    limits: &State<ClipLimits>,
+    metrics: &State<Metrics>,
+) -> Result<status::Created<String>, status::Custom<String>> {
+    use crate::domain::clip::field::{Content, ExpiresAt, Language, Password, Tags, Title};
+    use std::str::FromStr;
+
+    let bad_request = |msg: String| status::Custom(Status::BadRequest, format!("{}\n", msg));
+
+    let max_size = limits.max_content_size;
+    let content = content
+        .open(ByteUnit::from(max_size))
+        .into_string()
+        .await
+        .map_err(|err| bad_request(format!("failed to read content: {}", err)))?;
+
+    if !content.is_complete() {
+        return Err(bad_request(
+            ClipError::ContentTooLarge(max_size).to_string(),
+        ));
+    }
+
+    let req = Content::new(&content)
+        .and_then(|content| {
+            Ok(service::ask::NewClip {
+                content,
+                title: Title::new(options.title),
+                expires_at: ExpiresAt::from_str(options.expires_at.as_deref().unwrap_or_default())?,
+                password: Password::new(options.password)?,
+                tags: Tags::from_str(options.tags.as_deref().unwrap_or_default())?,
+                language: Language::new(options.language)?,
+            })
+        })
+        .and_then(|req| req.check(limits).map(|_| req))
+        .map_err(|err| bad_request(err.to_string()))?;
+
+    match action::new_clip(req, store, database.get_pool()).await {
+        Ok(clip) => {
+            metrics.count_created(1);
+            let url = format!(
+                "{}{}",
+                client.origin,
+                uri!(get_clip(short_code = clip.short_code))
+            );
+            Ok(status::Created::new(url.clone()).body(format!("{}\n", url)))
+        }
+        Err(err) => {
+            eprintln!("internal error: {}", err);
+            Err(status::Custom(
+                Status::InternalServerError,
+                "a server error occurred\n".to_owned(),
+            ))
+        }
+    }
+}
+
 #[rocket::post("/clip/<short_code>", data = "<form>")]
+#[allow(clippy::too_many_arguments)]
 async fn submit_clip_password(
     cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::PasswordProtectedClip>>,
     short_code: ShortCode,
     hit_counter: &State<HitCounter>,
+    visitor: Visitor,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<RawHtml<String>, PageError> {
     if let Some(form) = &form.value {
@@ -124,10 +285,11 @@ async fn submit_clip_password(
             password: form.password.clone(),
         };
 
-        match action::get_clip(req, database.get_pool()).await {
+        match action::get_clip(req, store, database.get_pool()).await {
             Ok(clip) => {
-                hit_counter.hit(short_code.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
+                hit_counter.hit(short_code.clone(), visitor);
+                let views = recent_views(&clip, database).await;
+                let context = ctx::ViewClip::new(clip).with_views(views.as_ref());
 
                 cookies.add(Cookie::new(
                     PASSWORD_COOKIE,
@@ -154,46 +316,371 @@ async fn submit_clip_password(
     }
 }
 
-#[rocket::get("/clip/raw/<short_code>")]
+/// Forks a clip and shows the copy. A protected clip needs its password
+/// cookie, so without it the visitor is sent to the password page instead.
+#[rocket::post("/clip/<short_code>/fork")]
+async fn fork_clip(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    metrics: &State<Metrics>,
+) -> Result<Redirect, PageError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.clone(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
+    };
+
+    match action::fork_clip(req, store, database.get_pool()).await {
+        Ok(clip) => {
+            metrics.count_created(1);
+            Ok(Redirect::to(uri!(get_clip(short_code = clip.short_code))))
+        }
+        Err(ServiceError::PermissionError(_)) => Ok(Redirect::to(uri!(get_clip(short_code)))),
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("clip not found".to_owned())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
+#[rocket::get("/collection/<short_code>")]
+async fn get_collection(
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    match action::get_collection(short_code.clone().into(), store, database.get_pool()).await {
+        Ok(collection) => {
+            let context = ctx::ViewCollection::new(collection);
+            Ok(status::Custom(
+                Status::Ok,
+                RawHtml(renderer.render(context, &[])),
+            ))
+        }
+        Err(ServiceError::PermissionError(_)) => {
+            let context = ctx::PasswordRequired::for_collection(short_code);
+            Ok(status::Custom(
+                Status::Unauthorized,
+                RawHtml(renderer.render(context, &[])),
+            ))
+        }
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("collection not found".to_owned())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
+#[rocket::post("/collection/<short_code>", data = "<form>")]
+async fn submit_collection_password(
+    cookies: &CookieJar<'_>,
+    form: Form<Contextual<'_, form::PasswordProtectedClip>>,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<RawHtml<String>, PageError> {
+    let Some(form) = &form.value else {
+        let context = ctx::PasswordRequired::for_collection(short_code);
+        return Ok(RawHtml(renderer.render(
+            context,
+            &["a password is required to view this collection"],
+        )));
+    };
+
+    let req = service::ask::GetCollection {
+        short_code: short_code.clone(),
+        password: form.password.clone(),
+    };
+
+    match action::get_collection(req, store, database.get_pool()).await {
+        Ok(collection) => {
+            cookies.add(Cookie::new(
+                PASSWORD_COOKIE,
+                form.password.clone().into_inner().unwrap_or_default(),
+            ));
+
+            Ok(RawHtml(
+                renderer.render(ctx::ViewCollection::new(collection), &[]),
+            ))
+        }
+        Err(ServiceError::PermissionError(err)) => {
+            let context = ctx::PasswordRequired::for_collection(short_code);
+            Ok(RawHtml(renderer.render(context, &[err.as_str()])))
+        }
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("collection not found".to_owned())),
+        Err(_) => Err(PageError::Internal("server error".to_owned())),
+    }
+}
+
+#[rocket::get("/clip/highlighted/<short_code>")]
+async fn get_highlighted_clip(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    hit_counter: &State<HitCounter>,
+    visitor: Visitor,
+    renderer: &State<Renderer<'_>>,
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.clone(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
+    };
+
+    show_clip(
+        req,
+        ctx::ViewClip::highlighted,
+        database,
+        store,
+        hit_counter,
+        visitor,
+        renderer,
+    )
+    .await
+}
+
+#[derive(rocket::Responder)]
+enum RawClip {
+    Content(Tagged<String>),
+    #[response(status = 401)]
+    Locked(String),
+}
+
+#[rocket::get("/clip/raw/<short_code>?<lines>")]
+#[allow(clippy::too_many_arguments)]
 async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     short_code: ShortCode,
+    lines: Option<&str>,
     database: &State<AppDatabase>,
+    store: &State<ContentStore>,
     hit_counter: &State<HitCounter>,
-) -> Result<status::Custom<String>, Status> {
+    visitor: Visitor,
+    if_none_match: IfNoneMatch,
+) -> Result<RawClip, Status> {
     use crate::domain::clip::field::Password;
 
+    let lines = lines
+        .map(str::parse::<form::LineRange>)
+        .transpose()
+        .map_err(|_| Status::BadRequest)?;
     let req = service::ask::GetClip {
         short_code: short_code.clone(),
         password: cookies
             .get(PASSWORD_COOKIE)
             .map(|c| c.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
     };
 
-    match action::get_clip(req, database.get_pool()).await {
+    match action::get_clip(req, store, database.get_pool()).await {
         Ok(clip) => {
-            hit_counter.hit(short_code.clone(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+            let version = clip.version;
+            let response = Tagged::or_not_modified(version, &if_none_match, || {
+                let content = clip.content.into_inner();
+                match lines {
+                    Some(lines) => lines.select(&content),
+                    None => content,
+                }
+            });
+            if let Tagged::Fresh(..) = response {
+                hit_counter.hit(short_code.clone(), visitor);
+            }
+            Ok(RawClip::Content(response))
         }
         Err(err) => match err {
-            ServiceError::PermissionError(msg) => Ok(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::PermissionError(msg) => Ok(RawClip::Locked(msg)),
             ServiceError::NotFound => Err(Status::NotFound),
             _ => Err(Status::InternalServerError),
         },
     }
 }
 
+/// What live viewers of a clip are sent when it changes.
+#[derive(serde::Serialize)]
+struct LiveClip {
+    content: String,
+    title: Option<String>,
+    expires_at: Option<Time>,
+    version: u64,
+}
+
+impl From<Clip> for LiveClip {
+    fn from(clip: Clip) -> Self {
+        Self {
+            content: clip.content.into_inner(),
+            title: clip.title.into_inner(),
+            expires_at: clip.expires_at.into_inner(),
+            version: clip.version.into_inner(),
+        }
+    }
+}
+
+/// Streams `updated` events while the clip changes, and a final `deleted`, or
+/// `locked` once it gets a password the viewer doesn't have.
+#[rocket::get("/clip/<short_code>/events", rank = 2)]
+async fn clip_events(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    events: &State<ClipEvents>,
+    mut shutdown: Shutdown,
+) -> Result<EventStream![], Status> {
+    use crate::domain::clip::field::Password;
+    use rocket::tokio::sync::broadcast::error::RecvError;
+
+    let password = cookies
+        .get(PASSWORD_COOKIE)
+        .map(|c| c.value())
+        .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+        .unwrap_or_default();
+
+    // Subscribe before checking the clip so no change slips in between.
+    let mut changes = events.subscribe();
+    let req = service::ask::GetClip {
+        short_code: short_code.clone(),
+        password: password.clone(),
+    };
+    match action::get_clip(req, store, database.get_pool()).await {
+        Ok(_) => (),
+        Err(ServiceError::PermissionError(_)) => return Err(Status::Unauthorized),
+        Err(ServiceError::NotFound) => return Err(Status::NotFound),
+        Err(_) => return Err(Status::InternalServerError),
+    }
+
+    Ok(EventStream! {
+        loop {
+            let change = rocket::tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(change) => change,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            if change.short_code() != &short_code {
+                continue;
+            }
+
+            match change {
+                ClipChange::Updated(clip)
+                    if clip.password.has_password() && clip.password != password =>
+                {
+                    yield Event::empty().event("locked");
+                    break;
+                }
+                ClipChange::Updated(clip) => {
+                    yield Event::json(&LiveClip::from(*clip)).event("updated");
+                }
+                ClipChange::Deleted(_) => {
+                    yield Event::empty().event("deleted");
+                    break;
+                }
+            }
+        }
+    })
+}
+
+#[derive(rocket::Responder)]
+struct AttachmentResponse {
+    content: Vec<u8>,
+    content_type: ContentType,
+    disposition: Header<'static>,
+    nosniff: Header<'static>,
+}
+
+impl AttachmentResponse {
+    fn new(attachment: Attachment, content: Vec<u8>) -> Self {
+        let content_type =
+            ContentType::parse_flexible(attachment.content_type()).unwrap_or(ContentType::Binary);
+
+        Self {
+            content,
+            content_type,
+            disposition: Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", attachment.file_name()),
+            ),
+            nosniff: Header::new("X-Content-Type-Options", "nosniff"),
+        }
+    }
+}
+
+#[rocket::get("/clip/file/<short_code>")]
+async fn get_clip_attachment(
+    cookies: &CookieJar<'_>,
+    short_code: ShortCode,
+    database: &State<AppDatabase>,
+    store: &State<ContentStore>,
+    hit_counter: &State<HitCounter>,
+    visitor: Visitor,
+) -> Result<AttachmentResponse, status::Custom<String>> {
+    use crate::domain::clip::field::Password;
+
+    let req = service::ask::GetClip {
+        short_code: short_code.clone(),
+        password: cookies
+            .get(PASSWORD_COOKIE)
+            .map(|c| c.value())
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
+    };
+
+    match action::get_attachment(req, store, database.get_pool()).await {
+        Ok((attachment, content)) => {
+            hit_counter.hit(short_code, visitor);
+            Ok(AttachmentResponse::new(attachment, content))
+        }
+        Err(err) => match err {
+            ServiceError::PermissionError(msg) => Err(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::NotFound => Err(status::Custom(
+                Status::NotFound,
+                "attachment not found".to_owned(),
+            )),
+            _ => Err(status::Custom(
+                Status::InternalServerError,
+                "server error".to_owned(),
+            )),
+        },
+    }
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![home, get_clip, new_clip, submit_clip_password, get_raw_clip]
+    rocket::routes![
+        home,
+        get_clip,
+        new_clip,
+        paste_clip,
+        submit_clip_password,
+        get_raw_clip,
+        get_highlighted_clip,
+        fork_clip,
+        clip_events,
+        get_clip_attachment,
+        get_collection,
+        submit_collection_password
+    ]
 }
 
 pub mod catcher {
+    use rocket::response::content::RawHtml;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
+    use crate::web::{ctx, renderer::Renderer};
+
     #[catch(default)]
     fn default(req: &Request) -> &'static str {
         eprintln!("unhandled request: {}", req);
@@ -211,8 +698,18 @@ pub mod catcher {
         "404"
     }
 
+    #[catch(413)]
+    fn payload_too_large(req: &Request) -> RawHtml<String> {
+        let error = "the clip is too large to be stashed";
+
+        match req.rocket().state::<Renderer<'static>>() {
+            Some(renderer) => RawHtml(renderer.render(ctx::Home::default(), &[error])),
+            None => RawHtml(error.to_owned()),
+        }
+    }
+
     pub fn catchers() -> Vec<Catcher> {
-        catchers![default, internal_error, not_found]
+        catchers![default, internal_error, not_found, payload_too_large]
     }
 }
 
@@ -242,7 +739,7 @@ pub mod test {
 
     #[test]
     fn requires_password_when_applicable() {
-        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+        use crate::domain::clip::field::Password;
         use crate::service;
         use rocket::http::{ContentType, Cookie};
 
@@ -250,14 +747,14 @@ pub mod test {
         let db = client.rocket().state::<AppDatabase>().unwrap();
 
         let req = service::ask::NewClip {
-            content: Content::new("content").unwrap(),
-            expires_at: ExpiresAt::default(),
             password: Password::new("123".to_owned()).unwrap(),
-            title: Title::default(),
+            ..service::ask::test::new_clip("content")
         };
 
         let clip = rt
-            .block_on(async move { service::action::new_clip(req, db.get_pool()).await })
+            .block_on(async move {
+                service::action::new_clip(req, &Default::default(), db.get_pool()).await
+            })
             .unwrap();
 
         let response = client
@@ -289,4 +786,362 @@ pub mod test {
             .dispatch();
         assert_eq!(response.status(), Status::Unauthorized);
     }
+
+    #[test]
+    fn rejects_oversized_clips_with_errors() {
+        use crate::domain::clip::limits::ClipLimits;
+        use rocket::http::ContentType;
+
+        let limits = ClipLimits::default();
+        let (_, client) = init_test_client();
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(format!(
+                "content=content&title={}&expires_at=&password=",
+                "t".repeat(limits.max_title_length + 1)
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("title exceeds the maximum length"));
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(format!(
+                "content={}&title=&expires_at=&password=",
+                "c".repeat(limits.max_content_size + 1)
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response
+            .into_string()
+            .unwrap()
+            .contains("content exceeds the maximum size"));
+    }
+
+    #[test]
+    fn uploads_and_serves_attachments() {
+        use rocket::http::{ContentType, Header};
+
+        let (_, client) = init_test_client();
+
+        let boundary = "clipstash-boundary";
+        let field = |name: &str, value: &str| {
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                boundary, name, value
+            )
+        };
+        let body = format!(
+            "{}{}{}{}--{}\r\nContent-Disposition: form-data; name=\"attachment\"; \
+             filename=\"../report.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n\
+             \x01\x02\x03\r\n--{}--\r\n",
+            field("content", ""),
+            field("title", ""),
+            field("expires_at", ""),
+            field("password", ""),
+            boundary,
+            boundary
+        );
+
+        let response = client
+            .post("/")
+            .header(Header::new(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        let response = client.get(format!("/clip/file{}", location)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Binary));
+        assert_eq!(
+            response.headers().get_one("Content-Disposition"),
+            Some("attachment; filename=\"report.bin\"")
+        );
+        assert_eq!(response.into_bytes().unwrap(), vec![1, 2, 3]);
+
+        let response = client.get(location).dispatch();
+        assert!(response.into_string().unwrap().contains("report.bin"));
+    }
+
+    #[test]
+    fn pastes_raw_bodies() {
+        use rocket::http::Header;
+
+        let rt = crate::test::async_runtime();
+        let mut config = crate::web::test::config(rt.handle());
+        config.public_url = crate::web::PublicUrl::new(Some("http://stash.local/".to_owned()));
+        let client = crate::web::test::client(config);
+
+        let response = client
+            .post("/?title=log&password=123")
+            .header(Header::new("Host", "attacker.example"))
+            .header(Header::new("X-Forwarded-Proto", "https"))
+            .body("line one\nline two\n")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let url = response.into_string().unwrap();
+        assert!(url.starts_with("http://stash.local/"));
+        assert!(url.ends_with('\n'));
+
+        let raw = url.trim_end().replace("http://stash.local/", "/clip/raw/");
+        let response = client
+            .get(raw)
+            .cookie(rocket::http::Cookie::new(PASSWORD_COOKIE, "123"))
+            .dispatch();
+        assert_eq!(response.into_string().unwrap(), "line one\nline two\n");
+
+        let response = client.post("/").body("   ").dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn raw_clips_are_tagged_with_their_version() {
+        use rocket::http::Header;
+
+        let (_rt, client) = init_test_client();
+
+        let response = client.post("/").body("cached").dispatch();
+        let url = response.into_string().unwrap();
+        let path = url.trim_end().rsplit('/').next().unwrap().to_owned();
+        let raw = format!("/clip/raw/{}", path);
+
+        let response = client.get(raw.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"1\""));
+
+        let response = client
+            .get(raw.clone())
+            .header(Header::new("If-None-Match", "\"1\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+
+        let response = client
+            .get(raw)
+            .header(Header::new("If-None-Match", "\"0\""))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn events_require_an_existing_clip() {
+        let (_rt, client) = init_test_client();
+
+        let response = client.get("/clip/missing/events").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn forks_clips_and_shows_their_lineage() {
+        use rocket::http::{ContentType, Cookie};
+
+        let (_rt, client) = init_test_client();
+        let paste = |query: &str| {
+            let response = client
+                .post(format!("/?{}", query))
+                .header(ContentType::Plain)
+                .body("[server]\nport = 80\n")
+                .dispatch();
+            assert_eq!(response.status(), Status::Created);
+            let url = response.into_string().unwrap();
+            url.trim().rsplit('/').next().unwrap().to_owned()
+        };
+
+        let original = paste("title=config");
+        let response = client.post(format!("/clip/{}/fork", original)).dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let fork = response
+            .headers()
+            .get_one("Location")
+            .unwrap()
+            .trim_start_matches('/')
+            .to_owned();
+        assert_ne!(fork, original);
+
+        let body = client
+            .get(format!("/{}", fork))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(body.contains(&format!(r#"href="/{}""#, original)));
+        assert!(body.contains("[server]"));
+        let body = client
+            .get(format!("/{}", original))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(body.contains(&format!(r#"href="/{}""#, fork)));
+
+        let protected = paste("password=123");
+        let response = client.post(format!("/clip/{}/fork", protected)).dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(format!("/{}", protected).as_str())
+        );
+        let response = client
+            .post(format!("/clip/{}/fork", protected))
+            .cookie(Cookie::new(PASSWORD_COOKIE, "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let protected_fork = response.headers().get_one("Location").unwrap().to_owned();
+        let response = client.get(protected_fork).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn collections_ask_for_their_password() {
+        use crate::domain::clip::field::{Password, Title};
+        use crate::service::{action, ask};
+        use rocket::http::ContentType;
+
+        let (rt, client) = init_test_client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+
+        let collection = rt
+            .block_on(async move {
+                let req = ask::NewClip {
+                    title: Title::new(Some("log".to_owned())),
+                    ..ask::test::new_clip("first line\nsecond line")
+                };
+                let clip = action::new_clip(req, &Default::default(), db.get_pool()).await?;
+
+                let req = ask::NewCollection {
+                    title: Title::new(Some("incident".to_owned())),
+                    password: Password::new("123".to_owned()).unwrap(),
+                    clips: vec![clip.short_code],
+                };
+                action::new_collection(req, &Default::default(), db.get_pool()).await
+            })
+            .unwrap();
+        let uri = format!("/collection/{}", collection.short_code.as_str());
+
+        let response = client.get(uri.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .post(uri.as_str())
+            .header(ContentType::Form)
+            .body("password=123")
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("incident"));
+        assert!(body.contains("first line\nsecond line"));
+
+        let response = client.get("/collection/missing").dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn highlights_clips_by_language() {
+        use rocket::http::{ContentType, Cookie};
+
+        let (_rt, client) = init_test_client();
+
+        let response = client
+            .post("/?language=rs&password=123")
+            .header(ContentType::Plain)
+            .body("fn main() {}\n")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let url = response.into_string().unwrap();
+        let short_code = url.trim().rsplit('/').next().unwrap();
+        let highlighted = format!("/clip/highlighted/{}", short_code);
+
+        let response = client.get(highlighted.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get(highlighted.as_str())
+            .cookie(Cookie::new(PASSWORD_COOKIE, "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("clip-code"));
+        assert!(body.contains("<span style="));
+
+        let response = client
+            .get(format!("/clip/raw/{}", short_code))
+            .cookie(Cookie::new(PASSWORD_COOKIE, "123"))
+            .dispatch();
+        assert_eq!(response.into_string().unwrap(), "fn main() {}\n");
+
+        let response = client
+            .post("/?language=klingon")
+            .header(ContentType::Plain)
+            .body("Qapla'")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn links_and_serves_line_ranges() {
+        use rocket::http::ContentType;
+
+        let (_rt, client) = init_test_client();
+
+        let response = client
+            .post("/")
+            .header(ContentType::Plain)
+            .body("a\nb\nc\n")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let url = response.into_string().unwrap();
+        let short_code = url.trim().rsplit('/').next().unwrap().to_owned();
+
+        let body = client
+            .get(format!("/{}", short_code))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(body.contains(r#"id="L2""#));
+        assert!(body.contains(r##"href="#L3""##));
+
+        let raw = |lines: &str| {
+            client
+                .get(format!("/clip/raw/{}?lines={}", short_code, lines))
+                .dispatch()
+        };
+        assert_eq!(raw("2-3").into_string().unwrap(), "b\nc\n");
+        assert_eq!(raw("1").into_string().unwrap(), "a\n");
+        assert_eq!(raw("3-9").into_string().unwrap(), "c\n");
+        assert_eq!(raw("0").status(), Status::BadRequest);
+        assert_eq!(raw("3-2").status(), Status::BadRequest);
+        assert_eq!(raw("x").status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn renders_markdown_clips() {
+        use rocket::http::ContentType;
+
+        let (_rt, client) = init_test_client();
+
+        let response = client
+            .post("/?language=markdown")
+            .header(ContentType::Plain)
+            .body("# Runbook\n\n<script>alert(1)</script>\n")
+            .dispatch();
+        let url = response.into_string().unwrap();
+        let short_code = url.trim().rsplit('/').next().unwrap();
+
+        let body = client
+            .get(format!("/{}", short_code))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(body.contains("<h1>Runbook</h1>"));
+        assert!(body.contains("toggle-source"));
+        assert!(!body.contains("<script>alert(1)</script>"));
+    }
 }
@@ -0,0 +1,45 @@
+use pulldown_cmark::{html, Options, Parser};
+
+/// Renders markdown to HTML that is safe to embed in a page: scripts, event
+/// handlers and other active content are stripped.
+pub fn render(content: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(content, options));
+
+    // Task lists render as checkboxes. Inputs are always disabled, so any
+    // other input slipped into a clip stays inert.
+    ammonia::Builder::default()
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked"])
+        .set_tag_attribute_value("input", "disabled", "")
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("input", "type") if value != "checkbox" => None,
+            _ => Some(value.into()),
+        })
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn renders_sanitized_html() {
+        let html = render("# Runbook\n\n- [x] restart\n\n<script>alert(1)</script>\n");
+        assert!(html.contains("<h1>Runbook</h1>"));
+        assert!(html.contains("checkbox"));
+        assert!(!html.contains("<script"));
+
+        let html =
+            render("[link](javascript:alert(1)) <img src=x onerror=alert(1)> <input type=text>");
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("onerror"));
+        assert!(!html.contains("text"));
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use parking_lot::Mutex;
+use rand::RngCore;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Data, Response, State};
+use sha2::Sha256;
+
+use crate::data::AppDatabase;
+use crate::domain::maintenance::Maintenance;
+
+use super::hit_counter::HitCounter;
+
+/// Upper bounds of the request latency histogram, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+#[derive(Debug, Default)]
+struct Latencies {
+    count: u64,
+    sum: Duration,
+    /// Requests per bucket, not cumulative.
+    buckets: [u64; BUCKETS.len()],
+}
+
+/// Method, route and status of the requests counted together.
+type RouteKey = (String, String, u16);
+
+/// Request counts and latencies per route, clips created through the web
+/// server, and the token `/metrics` needs. Without a token the endpoint is
+/// disabled.
+#[derive(Debug)]
+pub struct Metrics {
+    token: Option<String>,
+    /// Key the token is compared under, see `accepts`.
+    token_key: [u8; 32],
+    requests: Mutex<BTreeMap<RouteKey, Latencies>>,
+    created: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new(token: Option<String>) -> Self {
+        let mut token_key = [0; 32];
+        rand::thread_rng().fill_bytes(&mut token_key);
+
+        Self {
+            token: token.filter(|token| !token.is_empty()),
+            token_key,
+            requests: Mutex::new(BTreeMap::new()),
+            created: AtomicU64::new(0),
+        }
+    }
+
+    /// Compares HMACs of both tokens in constant time, so response times
+    /// reveal nothing about the configured token.
+    fn accepts(&self, token: &str, bearer: &str) -> bool {
+        let tag = |value: &str| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(&self.token_key)
+                .expect("HMAC accepts keys of any length");
+            mac.update(value.as_bytes());
+            mac
+        };
+
+        tag(bearer)
+            .verify_slice(&tag(token).finalize().into_bytes())
+            .is_ok()
+    }
+
+    /// Counts clips a route created, forks included.
+    pub fn count_created(&self, count: u64) {
+        self.created.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn record(&self, key: RouteKey, elapsed: Duration) {
+        let mut requests = self.requests.lock();
+        let latencies = requests.entry(key).or_default();
+        latencies.count += 1;
+        latencies.sum += elapsed;
+        if let Some(bucket) = BUCKETS
+            .iter()
+            .position(|bound| elapsed.as_secs_f64() <= *bound)
+        {
+            latencies.buckets[bucket] += 1;
+        }
+    }
+}
+
+/// When Rocket started handling a request.
+struct RequestStart(Instant);
+
+/// Counts requests and their latencies per route.
+pub struct MetricsFairing;
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request metrics",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        req.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let Some(metrics) = req.rocket().state::<Metrics>() else {
+            return;
+        };
+        let started = req.local_cache(|| RequestStart(Instant::now())).0;
+        let route = req
+            .route()
+            .map_or_else(|| "unmatched".to_owned(), |route| route.uri.to_string());
+
+        metrics.record(
+            (req.method().to_string(), route, res.status().code),
+            started.elapsed(),
+        );
+    }
+}
+
+/// Lets a request through to `/metrics` if it carries the configured
+/// bearer token.
+pub struct MetricsAuth;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsAuth {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let (metrics, token) = match req.rocket().state::<Metrics>() {
+            Some(
+                metrics @ Metrics {
+                    token: Some(token), ..
+                },
+            ) => (metrics, token),
+            _ => return Outcome::Error((Status::NotFound, ())),
+        };
+
+        let bearer = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match bearer {
+            Some(bearer) if metrics.accepts(token, bearer) => Outcome::Success(MetricsAuth),
+            _ => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Writes metric families in the Prometheus text format.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.0.push_str(&format!("# HELP {} {}\n", name, help));
+        self.0.push_str(&format!("# TYPE {} {}\n", name, kind));
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.0.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect();
+            self.0.push_str(&format!("{{{}}}", labels.join(",")));
+        }
+        self.0.push_str(&format!(" {}\n", value));
+    }
+
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: impl Display) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn requests(out: &mut Exposition, metrics: &Metrics) {
+    let requests = metrics.requests.lock();
+
+    out.family(
+        "clipstash_http_requests_total",
+        "counter",
+        "HTTP requests handled, by route and status.",
+    );
+    for ((method, route, status), latencies) in requests.iter() {
+        let status = status.to_string();
+        let labels = [
+            ("method", method.as_str()),
+            ("route", route.as_str()),
+            ("status", status.as_str()),
+        ];
+        out.sample("clipstash_http_requests_total", &labels, latencies.count);
+    }
+
+    out.family(
+        "clipstash_http_request_duration_seconds",
+        "histogram",
+        "Time taken to handle HTTP requests, by route.",
+    );
+    let mut per_route: BTreeMap<(&str, &str), Latencies> = BTreeMap::new();
+    for ((method, route, _), latencies) in requests.iter() {
+        let merged = per_route.entry((method, route)).or_default();
+        merged.count += latencies.count;
+        merged.sum += latencies.sum;
+        for (merged, bucket) in merged.buckets.iter_mut().zip(latencies.buckets) {
+            *merged += bucket;
+        }
+    }
+    for ((method, route), latencies) in per_route {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(latencies.buckets) {
+            cumulative += count;
+            let bound = bound.to_string();
+            let labels = [("method", method), ("route", route), ("le", bound.as_str())];
+            out.sample(
+                "clipstash_http_request_duration_seconds_bucket",
+                &labels,
+                cumulative,
+            );
+        }
+        let labels = [("method", method), ("route", route), ("le", "+Inf")];
+        out.sample(
+            "clipstash_http_request_duration_seconds_bucket",
+            &labels,
+            latencies.count,
+        );
+        let labels = [("method", method), ("route", route)];
+        out.sample(
+            "clipstash_http_request_duration_seconds_sum",
+            &labels,
+            latencies.sum.as_secs_f64(),
+        );
+        out.sample(
+            "clipstash_http_request_duration_seconds_count",
+            &labels,
+            latencies.count,
+        );
+    }
+}
+
+fn clips(
+    out: &mut Exposition,
+    metrics: &Metrics,
+    hit_counter: &HitCounter,
+    maintenance: &Maintenance,
+) {
+    out.single(
+        "clipstash_clips_created_total",
+        "counter",
+        "Clips created.",
+        metrics.created.load(Ordering::Relaxed),
+    );
+    out.single(
+        "clipstash_clips_read_total",
+        "counter",
+        "Clip views, including those dropped by a full hit queue.",
+        hit_counter.stats().received,
+    );
+    out.single(
+        "clipstash_clips_expired_total",
+        "counter",
+        "Clips moved to the trash after they expired.",
+        maintenance.stats().trashed,
+    );
+}
+
+fn maintenance(out: &mut Exposition, maintenance: &Maintenance) {
+    let stats = maintenance.stats();
+    out.family(
+        "clipstash_maintenance_run_duration_seconds",
+        "summary",
+        "Time taken by maintenance runs.",
+    );
+    out.sample(
+        "clipstash_maintenance_run_duration_seconds_sum",
+        &[],
+        stats.run_time.as_secs_f64(),
+    );
+    out.sample(
+        "clipstash_maintenance_run_duration_seconds_count",
+        &[],
+        stats.runs,
+    );
+    out.single(
+        "clipstash_maintenance_last_run_duration_seconds",
+        "gauge",
+        "Time taken by the latest maintenance run.",
+        stats.last_run_time.as_secs_f64(),
+    );
+
+    out.family(
+        "clipstash_maintenance_deleted_total",
+        "counter",
+        "Rows removed by maintenance, by task.",
+    );
+    for (task, deleted) in [
+        ("trash_expired", stats.trashed),
+        ("purge_trash", stats.purged),
+        ("forget_visitors", stats.visitors_forgotten),
+        ("delete_unreferenced_contents", stats.contents_deleted),
+    ] {
+        out.sample(
+            "clipstash_maintenance_deleted_total",
+            &[("task", task)],
+            deleted,
+        );
+    }
+}
+
+fn hit_counter(out: &mut Exposition, hit_counter: &HitCounter) {
+    let stats = hit_counter.stats();
+    out.single(
+        "clipstash_hit_queue_depth",
+        "gauge",
+        "Hits waiting in the queue to be counted.",
+        stats.queued,
+    );
+    out.single(
+        "clipstash_hits_dropped_total",
+        "counter",
+        "Hits dropped because the queue was full.",
+        stats.dropped,
+    );
+    out.single(
+        "clipstash_hits_committed_total",
+        "counter",
+        "Hits committed to the database.",
+        stats.committed,
+    );
+    out.family(
+        "clipstash_hit_flush_duration_seconds",
+        "summary",
+        "Time taken to commit batches of hits, failed ones included.",
+    );
+    out.sample(
+        "clipstash_hit_flush_duration_seconds_sum",
+        &[],
+        stats.flush_time.as_secs_f64(),
+    );
+    out.sample(
+        "clipstash_hit_flush_duration_seconds_count",
+        &[],
+        stats.flushes + stats.failed_flushes,
+    );
+    out.single(
+        "clipstash_hit_flushes_failed_total",
+        "counter",
+        "Batches of hits that failed to commit.",
+        stats.failed_flushes,
+    );
+    out.single(
+        "clipstash_hits_abandoned_total",
+        "counter",
+        "Hits given up on after failing to commit too many times.",
+        stats.abandoned,
+    );
+}
+
+fn database(out: &mut Exposition, database: &AppDatabase) {
+    let pool = database.get_pool();
+    let size = pool.size();
+    let idle = u32::try_from(pool.num_idle()).unwrap_or(u32::MAX);
+
+    out.family(
+        "clipstash_db_connections",
+        "gauge",
+        "Open database connections, by state.",
+    );
+    out.sample(
+        "clipstash_db_connections",
+        &[("state", "idle")],
+        idle.min(size),
+    );
+    out.sample(
+        "clipstash_db_connections",
+        &[("state", "in_use")],
+        size.saturating_sub(idle),
+    );
+    out.single(
+        "clipstash_db_max_connections",
+        "gauge",
+        "Connections the database pool may open.",
+        pool.options().get_max_connections(),
+    );
+}
+
+#[rocket::get("/metrics")]
+pub fn metrics(
+    _auth: MetricsAuth,
+    metrics: &State<Metrics>,
+    hit_counter: &State<HitCounter>,
+    maintenance: &State<Maintenance>,
+    database: &State<AppDatabase>,
+) -> (ContentType, String) {
+    let mut out = Exposition::default();
+    requests(&mut out, metrics);
+    clips(&mut out, metrics, hit_counter, maintenance);
+    self::maintenance(&mut out, maintenance);
+    self::hit_counter(&mut out, hit_counter);
+    self::database(&mut out, database);
+
+    let content_type = ContentType::new("text", "plain").with_params([("version", "0.0.4")]);
+    (content_type, out.0)
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![metrics]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Metrics;
+    use crate::test::async_runtime;
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn exposes_metrics_with_the_token() {
+        let rt = async_runtime();
+        let mut config = crate::web::test::config(rt.handle());
+        config.metrics = Metrics::new(Some("s3cret".to_owned()));
+        let client = crate::web::test::client(config);
+
+        let response = client.get("/").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let response = client
+            .post("/")
+            .header(ContentType::Plain)
+            .body("counted")
+            .dispatch();
+        assert_eq!(response.status(), Status::Created);
+
+        let response = client.get("/metrics").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get("/metrics")
+            .header(Header::new("Authorization", "Bearer wrong"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get("/metrics")
+            .header(Header::new("Authorization", "Bearer s3cret"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        for expected in [
+            "clipstash_http_requests_total{method=\"GET\",route=\"/\",status=\"200\"} 1",
+            "clipstash_http_request_duration_seconds_count{method=\"GET\",route=\"/\"} 1",
+            "clipstash_http_request_duration_seconds_bucket{method=\"GET\",route=\"/metrics\",le=\"+Inf\"} 2",
+            "# TYPE clipstash_clips_created_total counter",
+            "clipstash_clips_created_total 1",
+            "clipstash_hit_queue_depth 0",
+            "clipstash_maintenance_deleted_total{task=\"purge_trash\"}",
+            "clipstash_db_max_connections ",
+        ] {
+            assert!(body.contains(expected), "{} missing from\n{}", expected, body);
+        }
+    }
+
+    #[test]
+    fn is_disabled_without_a_token() {
+        let (_rt, client) = crate::web::test::init_test_client();
+
+        let response = client
+            .get("/metrics")
+            .header(Header::new("Authorization", "Bearer "))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
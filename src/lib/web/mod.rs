@@ -1,12 +1,39 @@
 pub mod api;
 pub mod ctx;
+pub mod etag;
 pub mod form;
+pub mod highlight;
 pub mod hit_counter;
 pub mod http;
+pub mod markdown;
+pub mod metrics;
+pub mod openapi;
 pub mod renderer;
+pub mod request_id;
+pub mod sparkline;
+pub mod visitor;
 
 pub const PASSWORD_COOKIE: &str = "password-protected-clip";
 
+/// The base URL clients reach the server at, used for absolute links. Without
+/// one, links point at the address the server listens on; request headers are
+/// never trusted for this.
+#[derive(Clone, Debug, Default)]
+pub struct PublicUrl(Option<String>);
+
+impl PublicUrl {
+    pub fn new(url: Option<String>) -> Self {
+        Self(url.map(|url| url.trim_end_matches('/').to_owned()))
+    }
+
+    pub fn origin(&self, config: &rocket::Config) -> String {
+        match &self.0 {
+            Some(url) => url.clone(),
+            None => format!("http://{}:{}", config.address, config.port),
+        }
+    }
+}
+
 #[derive(rocket::Responder)]
 pub enum PageError {
     #[response(status = 500)]
@@ -51,17 +78,37 @@ pub mod test {
 
         let renderer = Renderer::new("templates/".into());
         let database = crate::data::test::new_db(handle);
+        let events = crate::service::events::ClipEvents::default();
         let maintenance = crate::domain::maintenance::Maintenance::spawn(
+            database.get_pool().clone(),
+            Default::default(),
+            events.clone(),
+            handle.clone(),
+            Default::default(),
+        );
+        let hit_counter = HitCounter::new(
+            database.get_pool().clone(),
+            handle.clone(),
+            Default::default(),
+        );
+        let webhooks = crate::domain::webhook::Webhooks::spawn(
             database.get_pool().clone(),
             handle.clone(),
+            Default::default(),
         );
-        let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
 
         RocketConfig {
             renderer,
             database,
+            content_store: Default::default(),
+            events,
+            public_url: Default::default(),
+            visitor_key: Default::default(),
             hit_counter,
+            metrics: crate::web::metrics::Metrics::new(None),
+            limits: Default::default(),
             maintenance,
+            webhooks,
         }
     }
 
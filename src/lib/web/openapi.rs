@@ -0,0 +1,761 @@
+use rocket::serde::json::{Json, Value};
+use serde_json::json;
+
+use crate::domain::clip::field::{MAX_TAGS, MAX_TAG_LENGTH};
+use crate::domain::clip::stats::{DEFAULT_DAYS, MAX_DAYS};
+use crate::domain::collection::MAX_CLIPS;
+
+use super::api::{API_KEY_HEADER, MAX_BATCH_SIZE, MAX_LIST_SIZE};
+
+/// OpenAPI 3 description of the routes in [`super::api::routes`],
+/// [`super::api::webhook_routes`] and [`super::api::collection_routes`].
+pub fn document() -> Value {
+    let error = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ErrorBody" } } }
+        })
+    };
+    let body = |schema: &str| {
+        json!({
+            "required": true,
+            "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } }
+        })
+    };
+    let clip = |description: &str| {
+        json!({
+            "description": description,
+            "headers": { "ETag": { "$ref": "#/components/headers/ETag" } },
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Clip" } } }
+        })
+    };
+    let collection = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Collection" } } }
+        })
+    };
+    let language = json!({
+        "type": "string",
+        "nullable": true,
+        "description": "Syntax name or file extension, such as `rust` or `py`. When left out, the clip is highlighted as its `detected_language`."
+    });
+    let short_code = json!({
+        "name": "short_code",
+        "in": "path",
+        "required": true,
+        "schema": { "type": "string" }
+    });
+    let if_match = json!({
+        "name": "If-Match",
+        "in": "header",
+        "description": "Only apply the update if the clip still has one of these versions.",
+        "schema": { "type": "string" }
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "clipstash",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "security": [{ "ApiKey": [] }],
+        "paths": {
+            "/api/clip": {
+                "get": {
+                    "operationId": "listClips",
+                    "summary": "List the newest clips",
+                    "description": "Content is left out; fetch a clip by its short code to read it.",
+                    "parameters": [
+                        {
+                            "name": "tag",
+                            "in": "query",
+                            "description": "Only list clips carrying this tag.",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "limit",
+                            "in": "query",
+                            "schema": { "type": "integer", "minimum": 0, "maximum": MAX_LIST_SIZE }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Matching clips, newest first",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "type": "array",
+                                        "items": { "$ref": "#/components/schemas/ClipSummary" }
+                                    }
+                                }
+                            }
+                        },
+                        "400": error("Invalid tag or API key")
+                    }
+                },
+                "post": {
+                    "operationId": "newClip",
+                    "summary": "Create a clip",
+                    "requestBody": body("NewClip"),
+                    "responses": {
+                        "200": clip("The new clip"),
+                        "400": error("Invalid clip or API key"),
+                        "413": error("Request body too large")
+                    }
+                },
+                "put": {
+                    "operationId": "updateClip",
+                    "summary": "Replace every field of a clip",
+                    "parameters": [if_match],
+                    "requestBody": body("UpdateClip"),
+                    "responses": {
+                        "200": clip("The updated clip"),
+                        "400": error("Invalid clip or API key"),
+                        "404": error("Clip not found"),
+                        "412": error("Clip was modified since it was last read")
+                    }
+                }
+            },
+            "/api/clip/{short_code}": {
+                "get": {
+                    "operationId": "getClip",
+                    "summary": "Fetch a clip",
+                    "description": "Password protected clips need the password in the `password-protected-clip` cookie.",
+                    "parameters": [
+                        short_code,
+                        {
+                            "name": "If-None-Match",
+                            "in": "header",
+                            "schema": { "type": "string" }
+                        }
+                    ],
+                    "responses": {
+                        "200": clip("The clip"),
+                        "304": { "description": "The clip has not changed" },
+                        "400": error("Invalid API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Clip not found")
+                    }
+                },
+                "patch": {
+                    "operationId": "patchClip",
+                    "summary": "Change some fields of a clip",
+                    "parameters": [short_code, if_match],
+                    "requestBody": body("PatchClip"),
+                    "responses": {
+                        "200": clip("The updated clip"),
+                        "400": error("Invalid clip or API key"),
+                        "404": error("Clip not found"),
+                        "412": error("Clip was modified since it was last read")
+                    }
+                }
+            },
+            "/api/clip/{short_code}/fork": {
+                "post": {
+                    "operationId": "forkClip",
+                    "summary": "Copy a clip into a new clip",
+                    "description": "The fork keeps the content, title, tags, language, password and expiry date. A protected clip needs its password cookie.",
+                    "parameters": [short_code],
+                    "responses": {
+                        "200": clip("The new clip"),
+                        "400": error("Invalid API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Clip not found")
+                    }
+                }
+            },
+            "/api/clip/{short_code}/stats": {
+                "get": {
+                    "operationId": "getClipStats",
+                    "summary": "Views of a clip per day",
+                    "description": "A protected clip needs its password cookie.",
+                    "parameters": [
+                        short_code,
+                        {
+                            "name": "days",
+                            "in": "query",
+                            "description": "Days to cover, ending today (UTC).",
+                            "schema": { "type": "integer", "minimum": 1, "maximum": MAX_DAYS, "default": DEFAULT_DAYS }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The views of the clip",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/ClipStats" }
+                                }
+                            }
+                        },
+                        "400": error("Invalid API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Clip not found")
+                    }
+                }
+            },
+            "/api/clip/trash": {
+                "get": {
+                    "operationId": "listTrash",
+                    "summary": "List expired clips waiting to be purged",
+                    "description": "Most recently trashed first. Protected clips show only their short code and when they were trashed.",
+                    "responses": {
+                        "200": {
+                            "description": "The trashed clips",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": { "$ref": "#/components/schemas/TrashedClip" } }
+                                }
+                            }
+                        },
+                        "400": error("Invalid API key")
+                    }
+                }
+            },
+            "/api/clip/{short_code}/restore": {
+                "post": {
+                    "operationId": "restoreClip",
+                    "summary": "Take a clip out of the trash",
+                    "description": "The restored clip no longer expires. A protected clip needs its password cookie. Watchers and `clip.updated` webhooks are told of the restore.",
+                    "parameters": [short_code],
+                    "responses": {
+                        "200": clip("The restored clip"),
+                        "400": error("Invalid API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Clip not in the trash")
+                    }
+                }
+            },
+            "/api/clip/file": {
+                "post": {
+                    "operationId": "newClipWithAttachment",
+                    "summary": "Create a clip with an attached file",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "multipart/form-data": {
+                                "schema": {
+                                    "type": "object",
+                                    "properties": {
+                                        "content": { "type": "string" },
+                                        "title": { "type": "string" },
+                                        "expires_at": { "type": "string", "format": "date" },
+                                        "password": { "type": "string" },
+                                        "tags": { "type": "string", "description": "Comma separated." },
+                                        "language": { "type": "string" },
+                                        "attachment": { "type": "string", "format": "binary" }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": clip("The new clip"),
+                        "400": error("Invalid clip, upload or API key"),
+                        "413": error("Request body too large")
+                    }
+                }
+            },
+            "/api/clip/batch": {
+                "post": {
+                    "operationId": "newClips",
+                    "summary": "Create several clips at once",
+                    "description": "Either every clip is created or none of them is.",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "maxItems": MAX_BATCH_SIZE,
+                                    "items": { "$ref": "#/components/schemas/NewClip" }
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "The new clips, in request order",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": { "$ref": "#/components/schemas/Clip" } }
+                                }
+                            }
+                        },
+                        "400": error("Invalid clip, batch or API key"),
+                        "413": error("Request body too large")
+                    }
+                }
+            },
+            "/api/clip/batch-get": {
+                "post": {
+                    "operationId": "getClips",
+                    "summary": "Fetch several clips at once",
+                    "description": "Each clip is read on its own; failures are reported per item.",
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "maxItems": MAX_BATCH_SIZE,
+                                    "items": { "$ref": "#/components/schemas/GetClip" }
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "One item per requested clip, in request order",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": { "$ref": "#/components/schemas/BatchItem" } }
+                                }
+                            }
+                        },
+                        "400": error("Invalid batch or API key")
+                    }
+                }
+            },
+            "/api/clip/key": {
+                "get": {
+                    "operationId": "newApiKey",
+                    "summary": "Generate an API key",
+                    "description": "The key is written to the server log.",
+                    "security": [],
+                    "responses": {
+                        "200": {
+                            "description": "Confirmation that a key was generated",
+                            "content": { "application/json": { "schema": { "type": "string" } } }
+                        }
+                    }
+                }
+            },
+            "/api/collection": {
+                "post": {
+                    "operationId": "newCollection",
+                    "summary": "Bundle clips under one short code",
+                    "requestBody": body("NewCollection"),
+                    "responses": {
+                        "200": collection("The new collection"),
+                        "400": error("Invalid collection or API key")
+                    }
+                }
+            },
+            "/api/collection/{short_code}": {
+                "get": {
+                    "operationId": "getCollection",
+                    "summary": "Fetch a collection with previews of its clips",
+                    "description": "Password protected collections need the password in the `password-protected-clip` cookie.",
+                    "parameters": [short_code],
+                    "responses": {
+                        "200": collection("The collection"),
+                        "400": error("Invalid API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Collection not found")
+                    }
+                }
+            },
+            "/api/collection/{short_code}/clips": {
+                "put": {
+                    "operationId": "setCollectionClips",
+                    "summary": "Replace the clips of a collection, in the given order",
+                    "description": "Password protected collections need the password in the `password-protected-clip` cookie.",
+                    "parameters": [short_code],
+                    "requestBody": {
+                        "required": true,
+                        "content": {
+                            "application/json": {
+                                "schema": {
+                                    "type": "array",
+                                    "minItems": 1,
+                                    "maxItems": MAX_CLIPS,
+                                    "items": { "type": "string" }
+                                }
+                            }
+                        }
+                    },
+                    "responses": {
+                        "200": collection("The updated collection"),
+                        "400": error("Invalid clips or API key"),
+                        "401": error("Missing or wrong password"),
+                        "404": error("Collection not found")
+                    }
+                }
+            },
+            "/api/webhook": {
+                "post": {
+                    "operationId": "newWebhook",
+                    "summary": "Subscribe a URL to clip events",
+                    "description": "Deliveries are signed with the webhook secret: the `X-Clipstash-Signature` header holds `sha256=` followed by the hex HMAC-SHA256 of the body.",
+                    "requestBody": body("NewWebhook"),
+                    "responses": {
+                        "200": {
+                            "description": "The new webhook, including its secret",
+                            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Webhook" } } }
+                        },
+                        "400": error("Invalid webhook or API key")
+                    }
+                },
+                "get": {
+                    "operationId": "listWebhooks",
+                    "summary": "List webhooks",
+                    "responses": {
+                        "200": {
+                            "description": "Every webhook, without secrets",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": { "$ref": "#/components/schemas/Webhook" } }
+                                }
+                            }
+                        },
+                        "400": error("Invalid API key")
+                    }
+                }
+            },
+            "/api/webhook/{webhook_id}": {
+                "delete": {
+                    "operationId": "deleteWebhook",
+                    "summary": "Delete a webhook and its pending deliveries",
+                    "parameters": [{
+                        "name": "webhook_id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" }
+                    }],
+                    "responses": {
+                        "204": { "description": "The webhook was deleted" },
+                        "400": error("Invalid API key"),
+                        "404": error("Webhook not found")
+                    }
+                }
+            },
+            "/api/webhook/dead-letters": {
+                "get": {
+                    "operationId": "listDeadLetters",
+                    "summary": "List deliveries that were given up on",
+                    "responses": {
+                        "200": {
+                            "description": "Failed deliveries, most recent first",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "type": "array", "items": { "$ref": "#/components/schemas/DeadLetter" } }
+                                }
+                            }
+                        },
+                        "400": error("Invalid API key")
+                    }
+                }
+            }
+        },
+        "components": {
+            "securitySchemes": {
+                "ApiKey": { "type": "apiKey", "in": "header", "name": API_KEY_HEADER }
+            },
+            "headers": {
+                "ETag": {
+                    "description": "Version of the clip, for `If-Match` and `If-None-Match`.",
+                    "schema": { "type": "string" }
+                }
+            },
+            "schemas": {
+                "NewClip": {
+                    "type": "object",
+                    "required": ["content", "title", "expires_at", "password"],
+                    "properties": {
+                        "content": { "type": "string" },
+                        "title": { "type": "string", "nullable": true },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "password": { "type": "string", "nullable": true },
+                        "tags": { "$ref": "#/components/schemas/Tags" },
+                        "language": language.clone()
+                    }
+                },
+                "UpdateClip": {
+                    "type": "object",
+                    "required": ["short_code", "content", "title", "expires_at", "password"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "content": { "type": "string" },
+                        "title": { "type": "string", "nullable": true },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "password": { "type": "string", "nullable": true },
+                        "language": language.clone()
+                    }
+                },
+                "PatchClip": {
+                    "type": "object",
+                    "description": "Fields left out are kept, `null` clears them.",
+                    "properties": {
+                        "content": { "type": "string" },
+                        "title": { "type": "string", "nullable": true },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "password": { "type": "string", "nullable": true },
+                        "language": language.clone()
+                    }
+                },
+                "GetClip": {
+                    "type": "object",
+                    "required": ["short_code"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "password": { "type": "string", "nullable": true }
+                    }
+                },
+                "BatchItem": {
+                    "type": "object",
+                    "required": ["short_code"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "clip": { "$ref": "#/components/schemas/Clip" },
+                        "error": { "$ref": "#/components/schemas/ErrorBody" }
+                    }
+                },
+                "WebhookEvent": {
+                    "type": "string",
+                    "enum": ["clip.created", "clip.updated", "clip.first_viewed", "clip.expired"]
+                },
+                "NewWebhook": {
+                    "type": "object",
+                    "required": ["url"],
+                    "properties": {
+                        "url": { "type": "string", "format": "uri" },
+                        "secret": { "type": "string", "description": "Generated when left out." },
+                        "events": {
+                            "type": "array",
+                            "description": "Every event when empty or left out.",
+                            "items": { "$ref": "#/components/schemas/WebhookEvent" }
+                        }
+                    }
+                },
+                "Webhook": {
+                    "type": "object",
+                    "required": ["webhook_id", "url", "events", "created_at"],
+                    "properties": {
+                        "webhook_id": { "type": "string" },
+                        "url": { "type": "string", "format": "uri" },
+                        "events": { "type": "array", "items": { "$ref": "#/components/schemas/WebhookEvent" } },
+                        "created_at": { "type": "string", "format": "date-time" },
+                        "secret": { "type": "string" }
+                    }
+                },
+                "DeadLetter": {
+                    "type": "object",
+                    "required": ["delivery_id", "webhook_id", "url", "event", "payload", "attempts", "failed_at"],
+                    "properties": {
+                        "delivery_id": { "type": "string" },
+                        "webhook_id": { "type": "string" },
+                        "url": { "type": "string", "format": "uri" },
+                        "event": { "$ref": "#/components/schemas/WebhookEvent" },
+                        "payload": { "type": "string" },
+                        "attempts": { "type": "integer" },
+                        "last_error": { "type": "string", "nullable": true },
+                        "failed_at": { "type": "string", "format": "date-time" }
+                    }
+                },
+                "Attachment": {
+                    "type": "object",
+                    "required": ["file_name", "content_type", "size"],
+                    "properties": {
+                        "file_name": { "type": "string" },
+                        "content_type": { "type": "string" },
+                        "size": { "type": "integer", "format": "int64" }
+                    }
+                },
+                "Clip": {
+                    "type": "object",
+                    "required": ["short_code", "content", "title", "posted_at", "expires_at", "password", "hits", "version"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "content": { "type": "string" },
+                        "title": { "type": "string", "nullable": true },
+                        "posted_at": { "type": "string", "format": "date-time" },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "password": { "type": "string", "nullable": true },
+                        "hits": { "type": "integer", "format": "int64" },
+                        "version": { "type": "integer", "format": "int64" },
+                        "tags": { "$ref": "#/components/schemas/Tags" },
+                        "language": language,
+                        "detected_language": {
+                            "type": "string",
+                            "nullable": true,
+                            "description": "Guessed from the content when it was saved. Used when `language` is not set."
+                        },
+                        "forked_from": { "type": "string", "nullable": true, "description": "Short code of the clip this one was copied from." },
+                        "forks": {
+                            "type": "array",
+                            "description": "Short codes of unprotected copies of this clip.",
+                            "items": { "type": "string" }
+                        },
+                        "attachment": {
+                            "allOf": [{ "$ref": "#/components/schemas/Attachment" }],
+                            "nullable": true
+                        }
+                    }
+                },
+                "Tags": {
+                    "type": "array",
+                    "description": "Trimmed and lowercased; duplicates are dropped.",
+                    "maxItems": MAX_TAGS,
+                    "items": { "type": "string", "maxLength": MAX_TAG_LENGTH, "pattern": "^[^,]*$" }
+                },
+                "ClipStats": {
+                    "type": "object",
+                    "required": ["short_code", "hits", "days"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "hits": { "type": "integer", "format": "int64" },
+                        "days": {
+                            "type": "array",
+                            "description": "Oldest day first, including days without views.",
+                            "items": {
+                                "type": "object",
+                                "required": ["day", "views", "unique_views"],
+                                "properties": {
+                                    "day": { "type": "string", "format": "date" },
+                                    "views": { "type": "integer", "format": "int64" },
+                                    "unique_views": {
+                                        "type": "integer",
+                                        "format": "int64",
+                                        "description": "Views by different visitors, told apart by a hash of their address and user agent."
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "TrashedClip": {
+                    "type": "object",
+                    "required": ["short_code", "title", "expires_at", "deleted_at", "protected"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "title": { "type": "string", "nullable": true, "description": "Hidden for protected clips." },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "deleted_at": { "type": "string", "format": "date-time" },
+                        "protected": { "type": "boolean" }
+                    }
+                },
+                "ClipSummary": {
+                    "type": "object",
+                    "required": ["short_code", "title", "posted_at", "expires_at", "protected", "hits", "tags"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "title": { "type": "string", "nullable": true, "description": "Hidden for protected clips." },
+                        "posted_at": { "type": "string", "format": "date-time" },
+                        "expires_at": { "type": "string", "format": "date-time", "nullable": true },
+                        "protected": { "type": "boolean" },
+                        "hits": { "type": "integer", "format": "int64" },
+                        "tags": { "$ref": "#/components/schemas/Tags" }
+                    }
+                },
+                "NewCollection": {
+                    "type": "object",
+                    "required": ["clips"],
+                    "properties": {
+                        "title": { "type": "string", "nullable": true },
+                        "password": { "type": "string", "nullable": true },
+                        "clips": {
+                            "type": "array",
+                            "description": "Short codes of the member clips, in order.",
+                            "minItems": 1,
+                            "maxItems": MAX_CLIPS,
+                            "items": { "type": "string" }
+                        }
+                    }
+                },
+                "CollectionMember": {
+                    "type": "object",
+                    "required": ["short_code", "title", "protected", "preview", "truncated", "hits", "tags"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "title": { "type": "string", "nullable": true, "description": "Hidden for protected clips." },
+                        "protected": { "type": "boolean" },
+                        "preview": { "type": "string", "nullable": true, "description": "Start of the content, hidden for protected clips." },
+                        "truncated": { "type": "boolean" },
+                        "hits": { "type": "integer", "format": "int64" },
+                        "tags": { "$ref": "#/components/schemas/Tags" }
+                    }
+                },
+                "Collection": {
+                    "type": "object",
+                    "required": ["short_code", "title", "posted_at", "password", "clips"],
+                    "properties": {
+                        "short_code": { "type": "string" },
+                        "title": { "type": "string", "nullable": true },
+                        "posted_at": { "type": "string", "format": "date-time" },
+                        "password": { "type": "string", "nullable": true },
+                        "clips": { "type": "array", "items": { "$ref": "#/components/schemas/CollectionMember" } }
+                    }
+                },
+                "ErrorBody": {
+                    "type": "object",
+                    "required": ["code", "message", "request_id"],
+                    "properties": {
+                        "code": { "type": "string" },
+                        "message": { "type": "string" },
+                        "field": { "type": "string" },
+                        "request_id": { "type": "string" }
+                    }
+                }
+            }
+        }
+    })
+}
+
+#[rocket::get("/openapi.json")]
+fn openapi() -> Json<Value> {
+    Json(document())
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![openapi]
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::collections::BTreeSet;
+
+    use crate::web::test::init_test_client;
+
+    #[test]
+    fn document_matches_mounted_routes() {
+        let (_rt, client) = init_test_client();
+
+        let mounted: BTreeSet<(String, String)> = client
+            .rocket()
+            .routes()
+            .filter(|route| {
+                ["/api/clip", "/api/webhook", "/api/collection"].contains(&route.uri.base())
+            })
+            .map(|route| {
+                let path = route
+                    .uri
+                    .path()
+                    .trim_end_matches('/')
+                    .replace('<', "{")
+                    .replace('>', "}");
+                (path, route.method.as_str().to_lowercase())
+            })
+            .collect();
+
+        let document: serde_json::Value = client
+            .get("/api/openapi.json")
+            .dispatch()
+            .into_json()
+            .expect("failed to fetch openapi document");
+        let documented: BTreeSet<(String, String)> = document["paths"]
+            .as_object()
+            .expect("missing paths")
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .expect("missing operations")
+                    .keys()
+                    .map(move |method| (path.clone(), method.clone()))
+            })
+            .collect();
+
+        assert_eq!(mounted, documented);
+    }
+}
@@ -13,6 +13,8 @@ pub struct Renderer<'a>(handlebars::Handlebars<'a>);
 impl<'a> Renderer<'a> {
     pub fn new(template_dir: std::path::PathBuf) -> Self {
         let mut renderer = handlebars::Handlebars::new();
+        // Indenting partials would shift every line of clip content after the first.
+        renderer.set_prevent_indent(true);
 
         renderer
             .register_templates_directory(
@@ -32,7 +34,7 @@ impl<'a> Renderer<'a> {
     where
         S: serde::Serialize + std::fmt::Debug,
     {
-        serde_json::to_value(&serializable).expect("failed to convert to value")
+        serde_json::to_value(serializable).expect("failed to convert to value")
     }
 
     pub fn render<P>(&self, context: P, errors: &[&str]) -> String
@@ -0,0 +1,64 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Response};
+use std::convert::Infallible;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Identifies a request in logs and error bodies. A well-formed id sent by the
+/// client (or a proxy in front of us) is kept, otherwise a new one is generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn is_valid(id: &str) -> bool {
+        !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+    }
+
+    pub fn of(req: &Request<'_>) -> Self {
+        req.local_cache(|| {
+            let id = req
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .filter(|id| Self::is_valid(id))
+                .map(str::to_owned)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+            Self(id)
+        })
+        .clone()
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self::of(req))
+    }
+}
+
+/// Echoes the request id on every response.
+pub struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new(
+            REQUEST_ID_HEADER,
+            RequestId::of(req).into_inner(),
+        ));
+    }
+}
@@ -0,0 +1,72 @@
+use serde::Serialize;
+
+use crate::domain::clip::stats::ClipStats;
+
+const WIDTH: f64 = 120.0;
+const HEIGHT: f64 = 24.0;
+
+/// Daily views of a clip, drawn as an SVG polyline.
+#[derive(Debug, Serialize)]
+pub struct Sparkline {
+    width: f64,
+    height: f64,
+    points: String,
+    total: u64,
+    /// Unique views summed over the days, so a visitor who comes back on
+    /// another day is counted again.
+    unique: u64,
+    days: usize,
+}
+
+impl Sparkline {
+    pub fn new(stats: &ClipStats) -> Self {
+        let peak = stats.days.iter().map(|day| day.views).max().unwrap_or(0);
+        let step = WIDTH / stats.days.len().saturating_sub(1).max(1) as f64;
+        // Keep a pixel free at the top and bottom, so the line is not clipped.
+        let scale = |views: u64| match peak {
+            0 => HEIGHT - 1.0,
+            peak => HEIGHT - 1.0 - (views as f64 / peak as f64) * (HEIGHT - 2.0),
+        };
+
+        let points = stats
+            .days
+            .iter()
+            .enumerate()
+            .map(|(i, day)| format!("{:.1},{:.1}", i as f64 * step, scale(day.views)))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            width: WIDTH,
+            height: HEIGHT,
+            points,
+            total: stats.days.iter().map(|day| day.views).sum(),
+            unique: stats.days.iter().map(|day| day.unique_views).sum(),
+            days: stats.days.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::domain::clip::{field, stats::DailyViews};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn scales_views_to_the_busiest_day() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let recorded = vec![DailyViews {
+            day: today,
+            views: 4,
+            unique_views: 3,
+        }];
+        let stats = ClipStats::new("abc".into(), field::Hits::new(4), recorded, 3, today);
+
+        let sparkline = Sparkline::new(&stats);
+        assert_eq!(sparkline.points, "0.0,23.0 60.0,23.0 120.0,1.0");
+        assert_eq!(sparkline.total, 4);
+        assert_eq!(sparkline.unique, 3);
+        assert_eq!(sparkline.days, 3);
+    }
+}
@@ -0,0 +1,146 @@
+use chrono::{NaiveDate, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use sha2::Sha256;
+use std::convert::Infallible;
+use std::net::IpAddr;
+
+/// Secret the daily visitor salts are derived from. It is managed by Rocket;
+/// the server keeps it in the database so a restart doesn't count returning
+/// visitors again.
+#[derive(Clone)]
+pub struct VisitorKey(Vec<u8>);
+
+impl VisitorKey {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self(secret)
+    }
+
+    /// Each day gets its own salt, so hashes from different days cannot be
+    /// linked without the key.
+    fn salt(&self, day: NaiveDate) -> Vec<u8> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(day.to_string().as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+}
+
+/// A random key, for when there is no stored one.
+impl Default for VisitorKey {
+    fn default() -> Self {
+        let mut secret = vec![0; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self(secret)
+    }
+}
+
+impl std::fmt::Debug for VisitorKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VisitorKey(..)")
+    }
+}
+
+/// Whoever viewed a clip on `day`, as a salted hash of their IP address and
+/// user agent. The raw address is never kept.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Visitor {
+    day: NaiveDate,
+    hash: String,
+}
+
+impl Visitor {
+    /// Hashes with the salt of `day`, which is also the day the view counts
+    /// towards.
+    pub fn new(
+        key: &VisitorKey,
+        day: NaiveDate,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&key.salt(day))
+            .expect("HMAC accepts keys of any length");
+        mac.update(ip.map(|ip| ip.to_string()).unwrap_or_default().as_bytes());
+        mac.update(b"\0");
+        mac.update(user_agent.unwrap_or_default().as_bytes());
+
+        Self {
+            day,
+            hash: hex::encode(mac.finalize().into_bytes()),
+        }
+    }
+
+    pub fn day(&self) -> NaiveDate {
+        self.day
+    }
+
+    pub fn into_inner(self) -> String {
+        self.hash
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Visitor {
+    type Error = Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let key = req
+            .rocket()
+            .state::<VisitorKey>()
+            .expect("visitor key is managed");
+
+        Outcome::Success(Self::new(
+            key,
+            Utc::now().date_naive(),
+            req.client_ip(),
+            req.headers().get_one("User-Agent"),
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Visitor, VisitorKey};
+    use crate::data::test::new_db;
+    use crate::service::action;
+    use crate::test::async_runtime;
+    use chrono::Utc;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn hashes_without_revealing_the_address() {
+        let key = VisitorKey::default();
+        let day = Utc::now().date_naive();
+        let ip = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+
+        let visitor = Visitor::new(&key, day, ip, Some("curl/8.0"));
+        assert_eq!(visitor, Visitor::new(&key, day, ip, Some("curl/8.0")));
+        assert_ne!(visitor, Visitor::new(&key, day, ip, Some("Firefox")));
+        assert_ne!(visitor, Visitor::new(&key, day, None, Some("curl/8.0")));
+        let yesterday = day.pred_opt().unwrap();
+        assert_ne!(visitor, Visitor::new(&key, yesterday, ip, Some("curl/8.0")));
+        assert_eq!(visitor.day(), day);
+        assert!(!visitor.clone().into_inner().contains("192.0.2.1"));
+        assert_eq!(visitor.into_inner().len(), 64);
+    }
+
+    #[test]
+    fn stored_key_survives_restarts() {
+        let rt = async_runtime();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        let day = Utc::now().date_naive();
+
+        let (before, after) = rt.block_on(async {
+            let before = action::visitor_key(pool).await.unwrap();
+            let after = action::visitor_key(pool).await.unwrap();
+            (before, after)
+        });
+        assert_eq!(
+            Visitor::new(&before, day, None, Some("curl/8.0")),
+            Visitor::new(&after, day, None, Some("curl/8.0"))
+        );
+    }
+}